@@ -0,0 +1,196 @@
+//! Checking that a ring is consistent.
+//!
+//! An audit compares the state of every live node with the state a ring of
+//! exactly those nodes should have. Maintenance only converges on it: right
+//! after a failure, or while nodes join through
+//! [`Node::join_stabilized`], violations are expected until enough rounds
+//! of [`Node::tick`] have run.
+
+use crate::hash::Key;
+use crate::id::Identifier;
+use crate::node::{FingerTable, Node};
+use core::fmt;
+use std::collections::BTreeMap;
+
+/// A way in which a node's state differs from that of a consistent ring.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Violation<I> {
+    /// The successor of `node` is not among the live nodes.
+    DeadSuccessor { node: I, successor: I },
+    /// The successor of `node` does not take `node` as its predecessor.
+    PredecessorMismatch {
+        node: I,
+        successor: I,
+        predecessor: Option<I>,
+    },
+    /// Finger `index` of `node` is not the successor of its start.
+    WrongFinger {
+        node: I,
+        index: u32,
+        finger: Option<I>,
+        expected: I,
+    },
+    /// `node` stores a key that `owner` is responsible for.
+    MisplacedKey { key: Key<I>, node: I, owner: I },
+    /// More than one node stores the key as its owner.
+    DuplicateKey { key: Key<I>, nodes: Vec<I> },
+}
+
+impl<I: Identifier> fmt::Display for Violation<I> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Violation::DeadSuccessor { node, successor } => {
+                write!(f, "node {}: successor {} is not live", node, successor)
+            }
+            Violation::PredecessorMismatch {
+                node,
+                successor,
+                predecessor,
+            } => match predecessor {
+                Some(p) => write!(
+                    f,
+                    "node {}: successor {} has predecessor {}",
+                    node, successor, p
+                ),
+                None => write!(
+                    f,
+                    "node {}: successor {} has no predecessor",
+                    node, successor
+                ),
+            },
+            Violation::WrongFinger {
+                node,
+                index,
+                finger,
+                expected,
+            } => match finger {
+                Some(finger) => write!(
+                    f,
+                    "node {}: finger {} is {}, expected {}",
+                    node, index, finger, expected
+                ),
+                None => write!(
+                    f,
+                    "node {}: finger {} is empty, expected {}",
+                    node, index, expected
+                ),
+            },
+            Violation::MisplacedKey { key, node, owner } => {
+                write!(f, "node {}: key {} belongs to node {}", node, key, owner)
+            }
+            Violation::DuplicateKey { key, nodes } => {
+                let nodes: Vec<String> = nodes.iter().map(|n| n.to_string()).collect();
+                write!(f, "key {} is stored by nodes {}", key, nodes.join(", "))
+            }
+        }
+    }
+}
+
+/// The outcome of an audit.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AuditReport<I> {
+    /// Number of live nodes audited.
+    pub nodes: usize,
+    pub violations: Vec<Violation<I>>,
+}
+
+impl<I> AuditReport<I> {
+    pub fn is_healthy(&self) -> bool {
+        self.violations.is_empty()
+    }
+}
+
+impl<I: Identifier> fmt::Display for AuditReport<I> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} live nodes, {} violations",
+            self.nodes,
+            self.violations.len()
+        )?;
+        for v in self.violations.iter() {
+            write!(f, "\n  {}", v)?;
+        }
+        Ok(())
+    }
+}
+
+/// Audits the ring formed by the live nodes among `nodes`.
+///
+/// Nodes that left or crashed are ignored, except as the successor or
+/// finger of a live node. Replicas are not checked.
+pub fn audit<'a, I, V, const M: u32>(
+    nodes: impl IntoIterator<Item = &'a Node<I, V, M>>,
+) -> AuditReport<I>
+where
+    I: Identifier,
+    V: Clone + Send + 'static,
+{
+    let mut live: Vec<&Node<I, V, M>> = nodes.into_iter().filter(|n| n.is_alive()).collect();
+    live.sort_by_key(|n| n.id());
+    live.dedup_by_key(|n| n.id());
+    let ids: Vec<I> = live.iter().map(|n| n.id()).collect();
+    let successor_of = |id: I| match ids.iter().find(|n| **n >= id) {
+        Some(n) => *n,
+        None => ids[0],
+    };
+
+    let mut violations = Vec::new();
+    let mut holders: BTreeMap<Key<I>, Vec<I>> = BTreeMap::new();
+    for node in live.iter() {
+        let id = node.id();
+        let successor = node.successor_id();
+        match live.binary_search_by_key(&successor, |n| n.id()) {
+            Ok(i) => {
+                let predecessor = live[i].predecessor_id();
+                if predecessor != Some(id) {
+                    violations.push(Violation::PredecessorMismatch {
+                        node: id,
+                        successor,
+                        predecessor,
+                    });
+                }
+            }
+            Err(_) => violations.push(Violation::DeadSuccessor {
+                node: id,
+                successor,
+            }),
+        }
+
+        for (i, finger) in node.finger_ids().into_iter().enumerate() {
+            let index = i as u32 + 1;
+            let expected = successor_of(FingerTable::<I, V, M>::finger_id(id, index));
+            if finger != Some(expected) {
+                violations.push(Violation::WrongFinger {
+                    node: id,
+                    index,
+                    finger,
+                    expected,
+                });
+            }
+        }
+
+        for (key, _) in node.keys() {
+            let owner = successor_of(key.id);
+            if owner != id {
+                violations.push(Violation::MisplacedKey {
+                    key: key.clone(),
+                    node: id,
+                    owner,
+                });
+            }
+            holders.entry(key).or_default().push(id);
+        }
+    }
+    violations.extend(
+        holders
+            .into_iter()
+            .filter(|(_, nodes)| nodes.len() > 1)
+            .map(|(key, nodes)| Violation::DuplicateKey { key, nodes }),
+    );
+
+    AuditReport {
+        nodes: live.len(),
+        violations,
+    }
+}
@@ -0,0 +1,63 @@
+use crate::hash::HashFunction;
+use std::time::Duration;
+
+/// Tunables shared by every node of a ring.
+#[derive(Clone, Debug)]
+pub struct Config {
+    /// Hash function placing keys and node addresses on the ring.
+    pub hash_function: HashFunction,
+    /// Number of successors each node keeps to route around failures (`r` in the paper).
+    pub successor_list_len: usize,
+    /// Number of nodes storing each key: its owner and the next
+    /// `replication_factor - 1` successors. At most `successor_list_len + 1`.
+    pub replication_factor: usize,
+    /// How often the async runtime runs stabilization and repairs keys.
+    pub stabilize_interval: Duration,
+    /// How often the async runtime refreshes a finger.
+    pub fix_fingers_interval: Duration,
+    pub lookup_mode: LookupMode,
+    /// How long a lookup waits for each hop to answer. In recursive mode a
+    /// hop answers once the rest of the chain has, so the timeout must cover it.
+    pub hop_timeout: Duration,
+    /// What a joining node does if another node already has its identifier.
+    pub id_collision: IdCollision,
+}
+
+/// How a lookup travels from the querying node to the owner of a key.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum LookupMode {
+    /// The querying node asks every hop for the next one itself.
+    #[default]
+    Iterative,
+    /// Each hop forwards the query to the next one, and the answer travels
+    /// back along the chain. A hop that fails is routed around by the node
+    /// before it instead of failing the lookup.
+    Recursive,
+}
+
+/// How a node joining the ring resolves a clash with the identifier of a
+/// node already there.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum IdCollision {
+    /// The join fails with [`Error::DuplicateId`](crate::error::Error::DuplicateId).
+    #[default]
+    Reject,
+    /// The node takes a new identifier, hashing its address, or its old
+    /// identifier if it has none, together with a salt until the result is free.
+    Rehash,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            hash_function: HashFunction::default(),
+            successor_list_len: 4,
+            replication_factor: 1,
+            stabilize_interval: Duration::from_secs(1),
+            fix_fingers_interval: Duration::from_secs(1),
+            lookup_mode: LookupMode::default(),
+            hop_timeout: Duration::from_secs(1),
+            id_collision: IdCollision::default(),
+        }
+    }
+}
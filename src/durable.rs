@@ -0,0 +1,227 @@
+use crate::error::{Error, IoContext, Result};
+use crate::hash::Key;
+use crate::id::Identifier;
+use crate::storage::{MemoryStorage, Storage};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{BufRead, BufReader, Write},
+    path::{Path, PathBuf},
+};
+
+/// Number of logged writes after which the log is folded into a new snapshot.
+pub const DEFAULT_SNAPSHOT_INTERVAL: usize = 1024;
+
+const SNAPSHOT_FILE: &str = "snapshot.json";
+const LOG_FILE: &str = "wal.log";
+
+#[derive(Serialize, Deserialize)]
+enum LogRecord<I, V> {
+    Put(Key<I>, V),
+    Delete(Key<I>),
+}
+
+/// Crash-safe storage kept in a node's data directory.
+///
+/// Every write is appended to a write-ahead log and synced before it is
+/// applied. Every `snapshot_interval` writes the whole store is written to a
+/// snapshot and the log is truncated. Opening the directory again replays the
+/// snapshot and then the log, dropping a torn record at the end of the log.
+pub struct DurableStorage<I, V> {
+    dir: PathBuf,
+    log: File,
+    logged: usize,
+    snapshot_interval: usize,
+    cache: MemoryStorage<I, V>,
+}
+
+impl<I, V> DurableStorage<I, V>
+where
+    I: Identifier + Serialize + DeserializeOwned,
+    V: Clone + Send + Serialize + DeserializeOwned,
+{
+    /// Opens the data directory `dir`, creating it if needed and recovering
+    /// any keys persisted there.
+    pub fn open(dir: impl AsRef<Path>, snapshot_interval: usize) -> Result<Self> {
+        let dir = dir.as_ref().to_path_buf();
+        fs::create_dir_all(&dir)
+            .io_context(|| format!("cannot create data directory {}", dir.display()))?;
+
+        let mut cache = MemoryStorage::new();
+        let snapshot_path = dir.join(SNAPSHOT_FILE);
+        if snapshot_path.exists() {
+            let data = fs::read(&snapshot_path)
+                .io_context(|| format!("cannot read snapshot {}", snapshot_path.display()))?;
+            let entries: Vec<(Key<I>, V)> = serde_json::from_slice(&data)
+                .io_context(|| format!("corrupt snapshot {}", snapshot_path.display()))?;
+            for (k, v) in entries {
+                cache.put(k, v)?;
+            }
+        }
+
+        let log_path = dir.join(LOG_FILE);
+        let logged = Self::replay(&log_path, &mut cache)?;
+        let log = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&log_path)
+            .io_context(|| format!("cannot open log {}", log_path.display()))?;
+
+        Ok(Self {
+            dir,
+            log,
+            logged,
+            snapshot_interval: snapshot_interval.max(1),
+            cache,
+        })
+    }
+
+    /// Applies the records of the log at `path` to `cache` and returns how many
+    /// there were. A record cut short by a crash is truncated away.
+    fn replay(path: &Path, cache: &mut MemoryStorage<I, V>) -> Result<usize> {
+        if !path.exists() {
+            return Ok(0);
+        }
+        let context = || format!("cannot replay log {}", path.display());
+        let file = File::open(path).io_context(context)?;
+        let mut reader = BufReader::new(file);
+        let mut line = String::new();
+        let mut valid_len = 0u64;
+        let mut count = 0;
+        loop {
+            line.clear();
+            let read = reader.read_line(&mut line).io_context(context)?;
+            if read == 0 {
+                break;
+            }
+            let record = if line.ends_with('\n') {
+                serde_json::from_str::<LogRecord<I, V>>(&line).ok()
+            } else {
+                None
+            };
+            match record {
+                Some(LogRecord::Put(k, v)) => cache.put(k, v)?,
+                Some(LogRecord::Delete(k)) => {
+                    cache.delete(&k)?;
+                }
+                None => {
+                    // only the last record can be torn; anything else is corruption
+                    if reader.read_line(&mut String::new()).io_context(context)? != 0 {
+                        return Err(Error::Io(format!(
+                            "corrupt record in log {}",
+                            path.display()
+                        )));
+                    }
+                    OpenOptions::new()
+                        .write(true)
+                        .open(path)
+                        .and_then(|log| log.set_len(valid_len))
+                        .io_context(context)?;
+                    break;
+                }
+            }
+            valid_len += read as u64;
+            count += 1;
+        }
+        Ok(count)
+    }
+
+    fn append(&mut self, records: &[LogRecord<I, V>]) -> Result<()> {
+        let mut buf = Vec::new();
+        for record in records {
+            serde_json::to_writer(&mut buf, record)
+                .io_context(|| format!("cannot encode log record in {}", self.dir.display()))?;
+            buf.push(b'\n');
+        }
+        let context = || format!("cannot append to log in {}", self.dir.display());
+        let len = self.log.metadata().io_context(context)?.len();
+        if let Err(e) = self.log.write_all(&buf).and_then(|_| self.log.sync_data()) {
+            // drop any torn tail, which would otherwise stop the next replay
+            // at a record that is not the last one
+            let _ = self.log.set_len(len);
+            return Err(e).io_context(context);
+        }
+        self.logged += records.len();
+        Ok(())
+    }
+
+    fn maybe_snapshot(&mut self) -> Result<()> {
+        if self.logged >= self.snapshot_interval {
+            self.snapshot()?;
+        }
+        Ok(())
+    }
+
+    /// Writes every entry to a fresh snapshot and empties the log.
+    pub fn snapshot(&mut self) -> Result<()> {
+        let snapshot_path = self.dir.join(SNAPSHOT_FILE);
+        let tmp = snapshot_path.with_extension("tmp");
+        let entries = self.cache.range(I::zero(), I::zero());
+        let data = serde_json::to_vec(&entries)
+            .io_context(|| format!("cannot encode snapshot {}", tmp.display()))?;
+        File::create(&tmp)
+            .and_then(|mut file| {
+                file.write_all(&data)?;
+                file.sync_all()
+            })
+            .io_context(|| format!("cannot write snapshot {}", tmp.display()))?;
+        fs::rename(&tmp, &snapshot_path)
+            .io_context(|| format!("cannot replace snapshot {}", snapshot_path.display()))?;
+        // the snapshot now covers every logged record
+        self.log
+            .set_len(0)
+            .and_then(|_| self.log.sync_all())
+            .io_context(|| format!("cannot truncate log in {}", self.dir.display()))?;
+        self.logged = 0;
+        Ok(())
+    }
+}
+
+impl<I, V> Storage<I, V> for DurableStorage<I, V>
+where
+    I: Identifier + Serialize + DeserializeOwned,
+    V: Clone + Send + Serialize + DeserializeOwned,
+{
+    fn get(&self, key: &Key<I>) -> Option<V> {
+        self.cache.get(key)
+    }
+
+    fn put(&mut self, key: Key<I>, value: V) -> Result<()> {
+        self.append(&[LogRecord::Put(key.clone(), value.clone())])?;
+        self.cache.put(key, value)?;
+        self.maybe_snapshot()
+    }
+
+    fn delete(&mut self, key: &Key<I>) -> Result<Option<V>> {
+        if self.cache.get(key).is_none() {
+            return Ok(None);
+        }
+        self.append(&[LogRecord::Delete(key.clone())])?;
+        let value = self.cache.delete(key)?;
+        self.maybe_snapshot()?;
+        Ok(value)
+    }
+
+    fn range(&self, start: I, end: I) -> Vec<(Key<I>, V)> {
+        self.cache.range(start, end)
+    }
+
+    fn drain_range(&mut self, start: I, end: I) -> Result<Vec<(Key<I>, V)>> {
+        let entries = self.cache.range(start, end);
+        if entries.is_empty() {
+            return Ok(entries);
+        }
+        let records: Vec<_> = entries
+            .iter()
+            .map(|(k, _)| LogRecord::Delete(k.clone()))
+            .collect();
+        self.append(&records)?;
+        let drained = self.cache.drain_range(start, end)?;
+        self.maybe_snapshot()?;
+        Ok(drained)
+    }
+
+    fn len(&self) -> usize {
+        self.cache.len()
+    }
+}
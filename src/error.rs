@@ -0,0 +1,71 @@
+//! Errors returned by every fallible operation of a node.
+//!
+//! Identifiers and addresses are kept as text, so that one error type serves
+//! every identifier width and an error raised by a remote node reaches the
+//! caller unchanged in a [`crate::rpc::Response`].
+
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use thiserror::Error;
+
+#[derive(Clone, Debug, PartialEq, Eq, Error, Serialize, Deserialize)]
+pub enum Error {
+    /// The node has not joined a ring, or has left it or crashed.
+    #[error("node {node} is not part of a ring")]
+    NotJoined { node: String },
+    /// The node is already part of a ring, and has to leave it first.
+    #[error("node {node} is already part of a ring")]
+    AlreadyJoined { node: String },
+    /// Every successor the node knows of has failed.
+    #[error("node {node} has no live successor")]
+    NoSuccessor { node: String },
+    /// A [`Ring`](crate::Ring) has no node with this identifier.
+    #[error("no node {node} in the ring")]
+    UnknownNode { node: String },
+    /// A [`Ring`](crate::Ring) has no live node to carry out an operation.
+    #[error("the ring has no live node")]
+    EmptyRing,
+    /// Another node of the ring already has the identifier.
+    #[error("identifier {id} is already taken")]
+    DuplicateId { id: String },
+    /// An identifier or key lies outside the ring of `2^bits` identifiers.
+    #[error("identifier {id} does not fit in {bits} bits")]
+    IdOutOfRange { id: String, bits: u32 },
+    #[error("key {key} not found")]
+    KeyNotFound { key: String },
+    #[error("cannot reach {address}: {reason}")]
+    NodeUnreachable { address: String, reason: String },
+    #[error("no answer from {address} in time")]
+    Timeout { address: String },
+    /// A lookup came back to a node it had visited, as the routing state is
+    /// inconsistent.
+    #[error("lookup of {id} loops at node {node}")]
+    RoutingLoop { id: String, node: String },
+    /// The node needs an address and a transport to reach other nodes.
+    #[error("node {node} has no network address")]
+    NoAddress { node: String },
+    /// Reading or writing local files or sockets failed.
+    #[error("{0}")]
+    Io(String),
+    /// A message could not be encoded or decoded, or did not answer the request.
+    #[error("protocol error: {0}")]
+    Protocol(String),
+    #[error("invalid configuration: {0}")]
+    InvalidConfig(String),
+    /// The async runtime stopped before the operation could run.
+    #[error("the runtime is shutting down")]
+    Shutdown,
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// Turns a failed local I/O operation into an [`Error::Io`] describing it.
+pub(crate) trait IoContext<T> {
+    fn io_context(self, context: impl FnOnce() -> String) -> Result<T>;
+}
+
+impl<T, E: fmt::Display> IoContext<T> for std::result::Result<T, E> {
+    fn io_context(self, context: impl FnOnce() -> String) -> Result<T> {
+        self.map_err(|e| Error::Io(format!("{}: {}", context(), e)))
+    }
+}
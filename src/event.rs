@@ -0,0 +1,136 @@
+//! Events reported by nodes as the ring changes and keys are looked up.
+//!
+//! An [`Observer`] subscribed with [`Node::subscribe`] or [`Ring::subscribe`]
+//! receives every event of the node, in the order the changes happen.
+//!
+//! [`Node::subscribe`]: crate::node::Node::subscribe
+//! [`Ring::subscribe`]: crate::ring::Ring::subscribe
+
+use crate::hash::Key;
+use crate::id::Identifier;
+use core::fmt;
+use std::sync::{Mutex, MutexGuard, PoisonError};
+
+/// Something that happened on a node.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Event<I> {
+    /// `node` became part of the ring.
+    NodeJoined { node: I },
+    /// `node` left the ring after handing its keys on.
+    NodeLeft { node: I },
+    /// `node` stopped without telling the others.
+    NodeCrashed { node: I },
+    /// The predecessor of `node` changed, or was dropped if `None`.
+    PredecessorChanged { node: I, predecessor: Option<I> },
+    /// Finger `index` of `node` points to a new node; finger 1 is the
+    /// successor.
+    FingerUpdated { node: I, index: u32, finger: I },
+    /// `key` moved from node `from` to node `to`, which now owns it.
+    KeyMigrated { key: Key<I>, from: I, to: I },
+    /// A lookup of `key` started at `node` reached the key's owner.
+    LookupCompleted {
+        node: I,
+        key: Key<I>,
+        /// Identifiers of the nodes visited, from `node` to `owner`.
+        path: Vec<I>,
+        owner: I,
+        /// Whether the owner had a value for the key.
+        found: bool,
+    },
+}
+
+impl<I: Identifier> fmt::Display for Event<I> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Event::NodeJoined { node } => write!(f, "node {} joined the ring", node),
+            Event::NodeLeft { node } => write!(f, "node {} left the ring", node),
+            Event::NodeCrashed { node } => write!(f, "node {} crashed", node),
+            Event::PredecessorChanged { node, predecessor } => match predecessor {
+                Some(p) => write!(f, "node {}: predecessor is now {}", node, p),
+                None => write!(f, "node {}: predecessor dropped", node),
+            },
+            Event::FingerUpdated {
+                node,
+                index,
+                finger,
+            } => write!(f, "node {}: finger {} is now {}", node, index, finger),
+            Event::KeyMigrated { key, from, to } => {
+                write!(f, "migrate key {} from node {} to node {}", key, from, to)
+            }
+            Event::LookupCompleted {
+                node,
+                key,
+                path,
+                owner,
+                found,
+            } => {
+                let path: Vec<String> = path.iter().map(|id| id.to_string()).collect();
+                write!(
+                    f,
+                    "lookup of key {} from node {} via [{}]: {} at node {}",
+                    key,
+                    node,
+                    path.join(", "),
+                    if *found { "found" } else { "not found" },
+                    owner
+                )
+            }
+        }
+    }
+}
+
+/// Receives the events of the nodes it is subscribed to.
+///
+/// Events are delivered on the thread making the change, with no node lock
+/// held, so an observer may call back into the nodes.
+pub trait Observer<I>: Send + Sync {
+    fn notify(&self, event: &Event<I>);
+}
+
+impl<I, F> Observer<I> for F
+where
+    F: Fn(&Event<I>) + Send + Sync,
+{
+    fn notify(&self, event: &Event<I>) {
+        self(event)
+    }
+}
+
+/// An observer keeping every event it receives, oldest first.
+pub struct EventLog<I> {
+    events: Mutex<Vec<Event<I>>>,
+}
+
+impl<I: Clone> EventLog<I> {
+    pub fn new() -> Self {
+        Self {
+            events: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// The events received so far.
+    pub fn events(&self) -> Vec<Event<I>> {
+        self.lock().clone()
+    }
+
+    /// Removes and returns the events received so far.
+    pub fn take(&self) -> Vec<Event<I>> {
+        std::mem::take(&mut *self.lock())
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Vec<Event<I>>> {
+        self.events.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl<I: Clone> Default for EventLog<I> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<I: Clone + Send> Observer<I> for EventLog<I> {
+    fn notify(&self, event: &Event<I>) {
+        self.lock().push(event.clone());
+    }
+}
@@ -0,0 +1,166 @@
+//! Exporting the state of a ring, for pictures and for comparing rings.
+//!
+//! [`to_dot`] draws the live nodes as a Graphviz graph; [`snapshot`] records
+//! them as plain data, which [`to_json`] writes out. Both list the nodes in
+//! identifier order, so the same ring always exports to the same text.
+
+use crate::id::Identifier;
+use crate::node::{FingerTable, Node};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::f64::consts::PI;
+use std::fmt::Write;
+
+/// One finger of a node.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FingerSnapshot<I> {
+    /// From 1 to `M`; finger 1 is the successor.
+    pub index: u32,
+    pub start: I,
+    /// The end of the interval `[start, end)` the finger covers.
+    pub end: I,
+    pub node: Option<I>,
+}
+
+/// The state of a node.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NodeSnapshot<I> {
+    pub id: I,
+    pub predecessor: Option<I>,
+    pub successor: I,
+    pub successor_list: Vec<I>,
+    pub fingers: Vec<FingerSnapshot<I>>,
+    /// Number of keys the node owns, not counting replicas.
+    pub keys: usize,
+}
+
+/// The state of the live nodes of a ring, in identifier order.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RingSnapshot<I> {
+    /// Number of bits of the identifiers, `M`.
+    pub bits: u32,
+    pub nodes: Vec<NodeSnapshot<I>>,
+}
+
+/// Records the state of the live nodes among `nodes`.
+pub fn snapshot<'a, I, V, const M: u32>(
+    nodes: impl IntoIterator<Item = &'a Node<I, V, M>>,
+) -> RingSnapshot<I>
+where
+    I: Identifier,
+    V: Clone + Send + 'static,
+{
+    let nodes = live(nodes)
+        .into_iter()
+        .map(|node| {
+            let id = node.id();
+            let fingers = node
+                .finger_ids()
+                .into_iter()
+                .zip(1..=M)
+                .map(|(finger, index)| FingerSnapshot {
+                    index,
+                    start: FingerTable::<I, V, M>::finger_id(id, index),
+                    end: if index < M {
+                        FingerTable::<I, V, M>::finger_id(id, index + 1)
+                    } else {
+                        id
+                    },
+                    node: finger,
+                })
+                .collect();
+            NodeSnapshot {
+                id,
+                predecessor: node.predecessor_id(),
+                successor: node.successor_id(),
+                successor_list: node.successor_list(),
+                fingers,
+                keys: node.keys().len(),
+            }
+        })
+        .collect();
+    RingSnapshot { bits: M, nodes }
+}
+
+/// Writes the state of the live nodes among `nodes` as pretty-printed JSON;
+/// see [`RingSnapshot`].
+pub fn to_json<'a, I, V, const M: u32>(nodes: impl IntoIterator<Item = &'a Node<I, V, M>>) -> String
+where
+    I: Identifier + Serialize,
+    V: Clone + Send + 'static,
+{
+    // no maps or fallible fields, so serialization cannot fail
+    serde_json::to_string_pretty(&snapshot(nodes)).unwrap()
+}
+
+/// Draws the live nodes among `nodes` as a Graphviz graph.
+///
+/// The nodes are placed clockwise on a circle in identifier order, starting
+/// at the top; render with `neato -n` or `fdp` to keep the positions. Solid
+/// edges lead to successors, dashed ones to the other fingers, labelled with
+/// their indices.
+pub fn to_dot<'a, I, V, const M: u32>(nodes: impl IntoIterator<Item = &'a Node<I, V, M>>) -> String
+where
+    I: Identifier,
+    V: Clone + Send + 'static,
+{
+    let nodes = live(nodes);
+    let radius = 40.0 * nodes.len().max(3) as f64;
+    let mut dot = String::new();
+    // writing to a string cannot fail
+    writeln!(dot, "digraph ring {{").unwrap();
+    writeln!(dot, "  node [shape=circle];").unwrap();
+    for (i, node) in nodes.iter().enumerate() {
+        let angle = 2.0 * PI * i as f64 / nodes.len() as f64;
+        writeln!(
+            dot,
+            "  \"{}\" [label=\"{}\\nkeys: {}\", pos=\"{:.0},{:.0}!\"];",
+            node.id(),
+            node.id(),
+            node.keys().len(),
+            radius * angle.sin(),
+            radius * angle.cos()
+        )
+        .unwrap();
+    }
+    for node in nodes.iter() {
+        let id = node.id();
+        writeln!(dot, "  \"{}\" -> \"{}\";", id, node.successor_id()).unwrap();
+        // fingers sharing a node get one edge between them
+        let mut fingers: BTreeMap<I, Vec<u32>> = BTreeMap::new();
+        for (finger, index) in node.finger_ids().into_iter().zip(1..=M).skip(1) {
+            match finger {
+                Some(f) if f != id && f != node.successor_id() => {
+                    fingers.entry(f).or_default().push(index)
+                }
+                _ => {}
+            }
+        }
+        for (finger, indices) in fingers {
+            let indices: Vec<String> = indices.iter().map(|i| i.to_string()).collect();
+            writeln!(
+                dot,
+                "  \"{}\" -> \"{}\" [style=dashed, label=\"{}\"];",
+                id,
+                finger,
+                indices.join(",")
+            )
+            .unwrap();
+        }
+    }
+    dot.push_str("}\n");
+    dot
+}
+
+fn live<'a, I, V, const M: u32>(
+    nodes: impl IntoIterator<Item = &'a Node<I, V, M>>,
+) -> Vec<&'a Node<I, V, M>>
+where
+    I: Identifier,
+    V: Clone + Send + 'static,
+{
+    let mut live: Vec<&Node<I, V, M>> = nodes.into_iter().filter(|n| n.is_alive()).collect();
+    live.sort_by_key(|n| n.id());
+    live.dedup_by_key(|n| n.id());
+    live
+}
@@ -0,0 +1,67 @@
+use crate::id::Identifier;
+use core::fmt;
+use serde::{Deserialize, Serialize};
+use sha1::Sha1;
+use sha2::{Digest, Sha256};
+
+/// Hash function used to place keys and node addresses on the identifier circle.
+///
+/// Every node of a ring must use the same function, otherwise they disagree on
+/// which node owns a key.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum HashFunction {
+    /// SHA-1, as in the Chord paper.
+    #[default]
+    Sha1,
+    Sha256,
+}
+
+impl HashFunction {
+    pub fn digest(&self, data: &[u8]) -> Vec<u8> {
+        match self {
+            HashFunction::Sha1 => Sha1::digest(data).to_vec(),
+            HashFunction::Sha256 => Sha256::digest(data).to_vec(),
+        }
+    }
+
+    /// Maps `data` onto a ring of `2^bits` positions.
+    pub fn hash<I: Identifier>(&self, data: &[u8], bits: u32) -> I {
+        I::from_digest(&self.digest(data), bits)
+    }
+}
+
+/// A key as stored on the ring: its identifier together with the bytes it was
+/// hashed from.
+///
+/// Keys inserted directly by identifier have an empty `name`.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct Key<I> {
+    pub id: I,
+    pub name: Vec<u8>,
+}
+
+impl<I: Identifier> Key<I> {
+    pub fn from_id(id: I) -> Self {
+        Self {
+            id,
+            name: Vec::new(),
+        }
+    }
+
+    pub fn from_name(name: &[u8], hash_function: HashFunction, bits: u32) -> Self {
+        Self {
+            id: hash_function.hash(name, bits),
+            name: name.to_vec(),
+        }
+    }
+}
+
+impl<I: Identifier> fmt::Display for Key<I> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.name.is_empty() {
+            write!(f, "{}", self.id)
+        } else {
+            write!(f, "{:?}#{}", String::from_utf8_lossy(&self.name), self.id)
+        }
+    }
+}
@@ -0,0 +1,198 @@
+use core::fmt;
+use serde::{Deserialize, Serialize};
+use std::hash::Hash;
+
+/// An unsigned integer type usable as a position on the identifier circle.
+///
+/// Ring arithmetic is done modulo `2^bits`, where `bits` is the width of the
+/// identifier space and may be smaller than the width of the type itself.
+pub trait Identifier:
+    Copy + Eq + Ord + Hash + fmt::Debug + fmt::Display + Send + Sync + 'static
+{
+    /// Width of the underlying type in bits.
+    const BITS: u32;
+
+    fn zero() -> Self;
+
+    /// Returns `2^exp`; `exp` must be smaller than `BITS`.
+    fn pow2(exp: u32) -> Self;
+
+    /// Returns `(self + rhs) mod 2^bits`.
+    fn add_mod(self, rhs: Self, bits: u32) -> Self;
+
+    /// Returns `(self - rhs) mod 2^bits`.
+    fn sub_mod(self, rhs: Self, bits: u32) -> Self;
+
+    /// Interprets `digest` as a big-endian integer and reduces it modulo `2^bits`.
+    fn from_digest(digest: &[u8], bits: u32) -> Self;
+
+    /// Whether the value is below `2^bits`, a position of that ring.
+    fn fits(self, bits: u32) -> bool {
+        self.add_mod(Self::zero(), bits) == self
+    }
+}
+
+/// Returns whether `id` lies in the ring interval `(start, end]`.
+///
+/// `start == end` denotes the whole ring.
+pub fn in_interval<I: Ord>(id: I, start: I, end: I) -> bool {
+    if start < end {
+        start < id && id <= end
+    } else {
+        // the interval wraps around zero
+        start < id || id <= end
+    }
+}
+
+macro_rules! impl_identifier {
+    ($($t:ty),*) => {
+        $(
+            impl Identifier for $t {
+                const BITS: u32 = <$t>::BITS;
+
+                fn zero() -> Self {
+                    0
+                }
+
+                fn pow2(exp: u32) -> Self {
+                    assert!(exp < Self::BITS);
+                    1 << exp
+                }
+
+                fn add_mod(self, rhs: Self, bits: u32) -> Self {
+                    mask::<$t>(self.wrapping_add(rhs), bits)
+                }
+
+                fn sub_mod(self, rhs: Self, bits: u32) -> Self {
+                    mask::<$t>(self.wrapping_sub(rhs), bits)
+                }
+
+                fn from_digest(digest: &[u8], bits: u32) -> Self {
+                    let mut bytes = [0u8; (<$t>::BITS / 8) as usize];
+                    low_bytes(digest, &mut bytes);
+                    mask::<$t>(<$t>::from_be_bytes(bytes), bits)
+                }
+            }
+        )*
+    };
+}
+
+fn mask<T>(value: T, bits: u32) -> T
+where
+    T: Identifier + std::ops::BitAnd<Output = T> + std::ops::Sub<Output = T> + From<u8>,
+{
+    if bits >= T::BITS {
+        value
+    } else {
+        value & (T::pow2(bits) - T::from(1))
+    }
+}
+
+/// Copies the least significant bytes of the big-endian `digest` into `out`.
+fn low_bytes(digest: &[u8], out: &mut [u8]) {
+    let n = digest.len().min(out.len());
+    let out_len = out.len();
+    out[out_len - n..].copy_from_slice(&digest[digest.len() - n..]);
+}
+
+impl_identifier!(u8, u16, u32, u64, u128);
+
+/// A 160-bit unsigned integer, the identifier width used with SHA-1 in the Chord paper.
+///
+/// Stored big-endian so the derived ordering is the numeric one.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default, Serialize, Deserialize)]
+pub struct U160([u8; 20]);
+
+impl U160 {
+    pub const fn from_be_bytes(bytes: [u8; 20]) -> Self {
+        Self(bytes)
+    }
+
+    pub const fn to_be_bytes(self) -> [u8; 20] {
+        self.0
+    }
+
+    fn truncate(mut self, bits: u32) -> Self {
+        if bits >= Self::BITS {
+            return self;
+        }
+        // bytes[0] holds the most significant bits
+        let keep_bytes = bits.div_ceil(8) as usize;
+        let cleared = 20 - keep_bytes;
+        self.0[..cleared].fill(0);
+        if !bits.is_multiple_of(8) {
+            self.0[cleared] &= (1u8 << (bits % 8)) - 1;
+        }
+        self
+    }
+}
+
+impl From<u128> for U160 {
+    fn from(value: u128) -> Self {
+        let mut bytes = [0u8; 20];
+        bytes[4..].copy_from_slice(&value.to_be_bytes());
+        Self(bytes)
+    }
+}
+
+impl Identifier for U160 {
+    const BITS: u32 = 160;
+
+    fn zero() -> Self {
+        Self([0; 20])
+    }
+
+    fn pow2(exp: u32) -> Self {
+        assert!(exp < Self::BITS);
+        let mut bytes = [0u8; 20];
+        bytes[19 - (exp / 8) as usize] = 1 << (exp % 8);
+        Self(bytes)
+    }
+
+    fn add_mod(self, rhs: Self, bits: u32) -> Self {
+        let mut out = [0u8; 20];
+        let mut carry = 0u16;
+        for i in (0..20).rev() {
+            let sum = self.0[i] as u16 + rhs.0[i] as u16 + carry;
+            out[i] = sum as u8;
+            carry = sum >> 8;
+        }
+        Self(out).truncate(bits)
+    }
+
+    fn sub_mod(self, rhs: Self, bits: u32) -> Self {
+        let mut out = [0u8; 20];
+        let mut borrow = 0i16;
+        for i in (0..20).rev() {
+            let mut diff = self.0[i] as i16 - rhs.0[i] as i16 - borrow;
+            borrow = 0;
+            if diff < 0 {
+                diff += 256;
+                borrow = 1;
+            }
+            out[i] = diff as u8;
+        }
+        Self(out).truncate(bits)
+    }
+
+    fn from_digest(digest: &[u8], bits: u32) -> Self {
+        let mut bytes = [0u8; 20];
+        low_bytes(digest, &mut bytes);
+        Self(bytes).truncate(bits)
+    }
+}
+
+impl fmt::Display for U160 {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for byte in self.0 {
+            write!(f, "{:02x}", byte)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Debug for U160 {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "U160({})", self)
+    }
+}
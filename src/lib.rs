@@ -0,0 +1,58 @@
+//! Chord distributed hash table.
+//!
+//! A [`Node`] stores the keys of one arc of a ring of `2^M` identifiers and
+//! finds the owner of any key in `O(log n)` hops. Nodes sharing a process
+//! call each other directly, as in a [`Ring`]; nodes in other processes are
+//! reached through an [`rpc::Transport`] such as [`net::TcpTransport`], with
+//! a [`RemoteNode`] as the client handle of such a node.
+//!
+//! ```
+//! use chord::{Result, Ring};
+//!
+//! fn main() -> Result<()> {
+//!     let mut ring = Ring::<u8, String, 8>::new();
+//!     for id in [0, 80, 160] {
+//!         ring.add(id)?;
+//!     }
+//!     ring.insert(42, "hello".to_string())?;
+//!     let lookup = ring.lookup(42)?;
+//!     assert_eq!(lookup.owner, 80);
+//!     assert_eq!(lookup.value.as_deref(), Some("hello"));
+//!     Ok(())
+//! }
+//! ```
+
+pub mod audit;
+pub mod config;
+pub mod durable;
+pub mod error;
+pub mod event;
+pub mod export;
+pub mod hash;
+pub mod id;
+pub mod metrics;
+pub mod net;
+pub mod node;
+pub mod ring;
+pub mod rpc;
+#[cfg(feature = "async")]
+pub mod runtime;
+#[cfg(feature = "shell")]
+pub mod scenario;
+#[cfg(feature = "shell")]
+pub mod shell;
+pub mod sim;
+pub mod storage;
+mod test;
+
+pub use audit::{audit, AuditReport, Violation};
+pub use config::{Config, IdCollision, LookupMode};
+pub use error::{Error, Result};
+pub use event::{Event, EventLog, Observer};
+pub use hash::{HashFunction, Key};
+pub use id::{Identifier, U160};
+pub use metrics::MetricsSnapshot;
+pub use node::{Lookup, Node, RemoteNode};
+pub use ring::Ring;
+#[cfg(feature = "async")]
+pub use runtime::AsyncNode;
@@ -1,4 +1,94 @@
-mod node;
-mod test;
+use chord::net::{self, TcpTransport};
+use chord::shell::ShellNode;
+use chord::storage::MemoryStorage;
+use chord::{scenario, shell, Config, Error, RemoteNode, Result};
+use std::net::TcpListener;
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
+use std::time::Duration;
+use std::{env, panic, process, thread};
 
-fn main() {}
+const USAGE: &str = "usage: chord [run <scenario>... | serve <id> <address> [<bootstrap>]]";
+
+// how often a served node runs maintenance
+const TICK_INTERVAL: Duration = Duration::from_secs(1);
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    match args.split_first() {
+        None => {
+            if let Err(e) = shell::run() {
+                eprintln!("chord: {}", e);
+                process::exit(1);
+            }
+        }
+        Some((command, paths)) if command == "run" && !paths.is_empty() => {
+            if !scenario::run_files(paths) {
+                process::exit(1);
+            }
+        }
+        Some((command, [id, address, bootstrap @ ..]))
+            if command == "serve" && bootstrap.len() <= 1 =>
+        {
+            let Ok(id) = id.parse() else {
+                eprintln!("chord: {:?} is not a node identifier", id);
+                process::exit(2);
+            };
+            if let Err(e) = serve(id, address, bootstrap.first()) {
+                eprintln!("chord: {}", e);
+                process::exit(1);
+            }
+        }
+        _ => {
+            eprintln!("{}", USAGE);
+            process::exit(2);
+        }
+    }
+}
+
+/// Serves node `id` on `address` until the process is killed, after joining
+/// the ring through the node at `bootstrap`, or starting one if `None`.
+///
+/// Prints the address the node listens on once it has joined, which tells
+/// the actual port when `address` asks for any free one with port 0.
+fn serve(id: u8, address: &str, bootstrap: Option<&String>) -> Result<()> {
+    let listener = TcpListener::bind(address)
+        .and_then(|listener| Ok((listener.local_addr()?, listener)))
+        .map_err(|e| Error::Io(format!("cannot listen on {}: {}", address, e)));
+    let (address, listener) = listener?;
+    let transport = Arc::new(TcpTransport::default());
+    let node = ShellNode::with_transport(
+        id,
+        address.to_string(),
+        Config::default(),
+        Box::new(MemoryStorage::new()),
+        transport.clone(),
+    );
+    let stop = AtomicBool::new(false);
+    thread::scope(|scope| {
+        let server = scope.spawn(|| {
+            net::serve(&node, &listener, &stop, Some(TICK_INTERVAL), |e| {
+                eprintln!("{}", e)
+            })
+        });
+        if let Err(e) = join(&address.to_string(), bootstrap, transport) {
+            stop.store(true, Ordering::Relaxed);
+            let _ = server.join();
+            return Err(e);
+        }
+        println!("node {} listening on {}", id, address);
+        server.join().unwrap_or_else(|e| panic::resume_unwind(e))
+    })
+}
+
+/// Has the node served at `address` join the ring through the node at
+/// `bootstrap`, as a client would.
+fn join(address: &str, bootstrap: Option<&String>, transport: Arc<TcpTransport>) -> Result<()> {
+    let node = RemoteNode::<u8, String, 8>::connect(address, transport.clone())?;
+    let bootstrap = bootstrap
+        .map(|b| RemoteNode::connect(b, transport.clone()))
+        .transpose()?;
+    node.join(bootstrap.as_ref())
+}
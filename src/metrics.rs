@@ -0,0 +1,284 @@
+//! Per-node counters, and their export in the Prometheus text format.
+//!
+//! Every node counts its work as it goes; [`Node::metrics`] reads the
+//! counters at one point in time. [`to_prometheus`] renders those of a set of
+//! nodes, labelled by node, and [`serve_metrics`] answers scrapes of them
+//! over HTTP.
+//!
+//! [`Node::metrics`]: crate::node::Node::metrics
+
+use crate::error::Result;
+use crate::id::Identifier;
+use crate::net::accept_connections;
+use crate::node::Node;
+use serde::{Deserialize, Serialize};
+use std::fmt::Write as _;
+use std::{
+    io::{self, BufRead, BufReader, Write},
+    net::{TcpListener, TcpStream},
+    sync::atomic::{AtomicBool, AtomicU64, Ordering},
+    time::Duration,
+};
+
+/// Upper bounds of the buckets of the hop count histogram.
+pub const HOP_BUCKETS: [u64; 8] = [0, 1, 2, 3, 4, 6, 8, 16];
+
+// reads one value out of a snapshot
+type Sample = fn(&MetricsSnapshot) -> u64;
+
+/// The counters of one node, shared with the remote handles it creates.
+#[derive(Debug, Default)]
+pub(crate) struct Metrics {
+    pub(crate) lookups_initiated: AtomicU64,
+    pub(crate) lookups_served: AtomicU64,
+    // one bucket per bound in `HOP_BUCKETS`, then one for longer paths
+    hops: [AtomicU64; HOP_BUCKETS.len() + 1],
+    hops_sum: AtomicU64,
+    pub(crate) keys_migrated_in: AtomicU64,
+    pub(crate) keys_migrated_out: AtomicU64,
+    pub(crate) finger_updates: AtomicU64,
+    pub(crate) rpcs_sent: AtomicU64,
+    pub(crate) rpcs_received: AtomicU64,
+}
+
+impl Metrics {
+    pub(crate) fn count(counter: &AtomicU64, n: usize) {
+        counter.fetch_add(n as u64, Ordering::Relaxed);
+    }
+
+    pub(crate) fn record_hops(&self, hops: usize) {
+        let hops = hops as u64;
+        let bucket = HOP_BUCKETS
+            .iter()
+            .position(|bound| hops <= *bound)
+            .unwrap_or(HOP_BUCKETS.len());
+        self.hops[bucket].fetch_add(1, Ordering::Relaxed);
+        self.hops_sum.fetch_add(hops, Ordering::Relaxed);
+    }
+
+    pub(crate) fn snapshot(&self, keys_held: usize) -> MetricsSnapshot {
+        let load = |counter: &AtomicU64| counter.load(Ordering::Relaxed);
+        let mut count = 0;
+        let mut buckets = Vec::with_capacity(HOP_BUCKETS.len());
+        for (bound, bucket) in HOP_BUCKETS.iter().zip(self.hops.iter()) {
+            count += load(bucket);
+            buckets.push((*bound, count));
+        }
+        count += load(&self.hops[HOP_BUCKETS.len()]);
+        MetricsSnapshot {
+            lookups_initiated: load(&self.lookups_initiated),
+            lookups_served: load(&self.lookups_served),
+            hops: Histogram {
+                buckets,
+                count,
+                sum: load(&self.hops_sum),
+            },
+            keys_held: keys_held as u64,
+            keys_migrated_in: load(&self.keys_migrated_in),
+            keys_migrated_out: load(&self.keys_migrated_out),
+            finger_updates: load(&self.finger_updates),
+            rpcs_sent: load(&self.rpcs_sent),
+            rpcs_received: load(&self.rpcs_received),
+        }
+    }
+}
+
+/// Observations counted into buckets, as a Prometheus histogram.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Histogram {
+    /// `(bound, count)` pairs, where `count` is the number of observations
+    /// of at most `bound`.
+    pub buckets: Vec<(u64, u64)>,
+    /// Number of observations.
+    pub count: u64,
+    /// Sum of the observations.
+    pub sum: u64,
+}
+
+/// The counters of a node at one point in time.
+///
+/// Everything but `keys_held` counts up from the node's creation.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MetricsSnapshot {
+    /// Lookups of keys started by the node, as by [`Node::lookup`].
+    pub lookups_initiated: u64,
+    /// Reads of keys the node answered as their owner.
+    pub lookups_served: u64,
+    /// Hops taken by every search for a successor the node started,
+    /// including those of joins and maintenance.
+    pub hops: Histogram,
+    /// Keys the node owns, not counting replicas.
+    pub keys_held: u64,
+    /// Keys taken over from the successor when joining.
+    pub keys_migrated_in: u64,
+    /// Keys handed to a joining predecessor, to the successor when leaving,
+    /// or back towards their owner during maintenance.
+    pub keys_migrated_out: u64,
+    /// Fingers, the successor included, pointed to a different node.
+    pub finger_updates: u64,
+    /// Requests sent to remote nodes.
+    pub rpcs_sent: u64,
+    /// Requests answered for remote nodes and clients.
+    pub rpcs_received: u64,
+}
+
+/// Renders the metrics of the live nodes among `nodes` in the Prometheus
+/// text format, each sample labelled with the node's identifier.
+pub fn to_prometheus<'a, I, V, const M: u32>(
+    nodes: impl IntoIterator<Item = &'a Node<I, V, M>>,
+) -> String
+where
+    I: Identifier,
+    V: Clone + Send + 'static,
+{
+    let mut nodes: Vec<(I, MetricsSnapshot)> = nodes
+        .into_iter()
+        .filter(|n| n.is_alive())
+        .map(|n| (n.id(), n.metrics()))
+        .collect();
+    nodes.sort_by_key(|(id, _)| *id);
+
+    let counters: [(&str, &str, &str, Sample); 8] = [
+        (
+            "chord_lookups_initiated_total",
+            "counter",
+            "Lookups of keys started by the node.",
+            |m| m.lookups_initiated,
+        ),
+        (
+            "chord_lookups_served_total",
+            "counter",
+            "Reads of keys the node answered as their owner.",
+            |m| m.lookups_served,
+        ),
+        (
+            "chord_keys_held",
+            "gauge",
+            "Keys the node owns, not counting replicas.",
+            |m| m.keys_held,
+        ),
+        (
+            "chord_keys_migrated_in_total",
+            "counter",
+            "Keys taken over from the successor when joining.",
+            |m| m.keys_migrated_in,
+        ),
+        (
+            "chord_keys_migrated_out_total",
+            "counter",
+            "Keys handed over to other nodes.",
+            |m| m.keys_migrated_out,
+        ),
+        (
+            "chord_finger_updates_total",
+            "counter",
+            "Fingers pointed to a different node.",
+            |m| m.finger_updates,
+        ),
+        (
+            "chord_rpcs_sent_total",
+            "counter",
+            "Requests sent to remote nodes.",
+            |m| m.rpcs_sent,
+        ),
+        (
+            "chord_rpcs_received_total",
+            "counter",
+            "Requests answered for remote nodes and clients.",
+            |m| m.rpcs_received,
+        ),
+    ];
+    // writing to a string cannot fail
+    let mut text = String::new();
+    for (name, kind, help, value) in counters {
+        writeln!(text, "# HELP {} {}", name, help).unwrap();
+        writeln!(text, "# TYPE {} {}", name, kind).unwrap();
+        for (id, metrics) in nodes.iter() {
+            writeln!(text, "{}{{node=\"{}\"}} {}", name, id, value(metrics)).unwrap();
+        }
+    }
+
+    let name = "chord_lookup_hops";
+    writeln!(
+        text,
+        "# HELP {} Hops taken by the searches for a successor the node started.",
+        name
+    )
+    .unwrap();
+    writeln!(text, "# TYPE {} histogram", name).unwrap();
+    for (id, metrics) in nodes.iter() {
+        let hops = &metrics.hops;
+        for (bound, count) in hops.buckets.iter() {
+            writeln!(
+                text,
+                "{}_bucket{{node=\"{}\",le=\"{}\"}} {}",
+                name, id, bound, count
+            )
+            .unwrap();
+        }
+        writeln!(
+            text,
+            "{}_bucket{{node=\"{}\",le=\"+Inf\"}} {}",
+            name, id, hops.count
+        )
+        .unwrap();
+        writeln!(text, "{}_sum{{node=\"{}\"}} {}", name, id, hops.sum).unwrap();
+        writeln!(text, "{}_count{{node=\"{}\"}} {}", name, id, hops.count).unwrap();
+    }
+    text
+}
+
+/// Answers HTTP requests for `/metrics` on `listener` with the text `render`
+/// returns, until `stop` is set.
+///
+/// Requests are answered one at a time, as scrapes are rare and quick.
+pub fn serve_metrics(
+    listener: &TcpListener,
+    stop: &AtomicBool,
+    render: impl Fn() -> String,
+) -> Result<()> {
+    accept_connections(
+        listener,
+        stop,
+        |stream| {
+            // a client that goes away only loses its own answer
+            let _ = answer(stream, &render);
+        },
+        |_| {},
+    )
+}
+
+fn answer(stream: TcpStream, render: &impl Fn() -> String) -> io::Result<()> {
+    stream.set_nonblocking(false)?;
+    stream.set_read_timeout(Some(Duration::from_secs(5)))?;
+    let mut reader = BufReader::new(&stream);
+    let mut request = String::new();
+    reader.read_line(&mut request)?;
+    // skip the headers, up to the blank line ending them
+    let mut header = String::new();
+    while reader.read_line(&mut header)? > 0 && !header.trim().is_empty() {
+        header.clear();
+    }
+    let mut words = request.split_whitespace();
+    let (status, content_type, body) = match (words.next(), words.next()) {
+        (Some("GET"), Some("/metrics")) => (
+            "200 OK",
+            "text/plain; version=0.0.4; charset=utf-8",
+            render(),
+        ),
+        (Some("GET"), _) => ("404 Not Found", "text/plain", "not found\n".to_string()),
+        _ => (
+            "405 Method Not Allowed",
+            "text/plain",
+            "only GET is supported\n".to_string(),
+        ),
+    };
+    write!(
+        &stream,
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        content_type,
+        body.len(),
+        body
+    )
+}
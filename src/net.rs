@@ -0,0 +1,215 @@
+//! Running nodes over TCP.
+//!
+//! Each request is sent on a fresh connection as one line of JSON, answered
+//! by one line of JSON holding the response.
+
+use crate::error::{Error, IoContext, Result};
+use crate::id::Identifier;
+use crate::node::Node;
+use crate::rpc::{Request, Response, Transport};
+use core::fmt;
+use serde::{de::DeserializeOwned, Serialize};
+use std::{
+    io::{self, BufRead, BufReader, Write},
+    net::{TcpListener, TcpStream, ToSocketAddrs},
+    sync::atomic::{AtomicBool, Ordering},
+    thread,
+    time::{Duration, Instant},
+};
+
+/// How long a request may take before the peer is considered failed.
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(5);
+
+// how often the servers check for new connections and the stop flag
+const POLL_INTERVAL: Duration = Duration::from_millis(5);
+
+/// Sends requests to nodes listening on TCP addresses.
+#[derive(Clone, Debug)]
+pub struct TcpTransport {
+    timeout: Duration,
+}
+
+impl TcpTransport {
+    pub fn new(timeout: Duration) -> Self {
+        Self { timeout }
+    }
+}
+
+impl Default for TcpTransport {
+    fn default() -> Self {
+        Self::new(DEFAULT_TIMEOUT)
+    }
+}
+
+impl<I, V> Transport<I, V> for TcpTransport
+where
+    I: Serialize + DeserializeOwned,
+    V: Serialize + DeserializeOwned,
+{
+    fn call(&self, address: &str, request: Request<I, V>) -> Result<Response<I, V>> {
+        self.call_with_timeout(address, request, self.timeout)
+    }
+
+    fn call_with_timeout(
+        &self,
+        address: &str,
+        request: Request<I, V>,
+        timeout: Duration,
+    ) -> Result<Response<I, V>> {
+        let stream = address
+            .to_socket_addrs()
+            .and_then(|mut addrs| {
+                addrs.next().ok_or_else(|| {
+                    io::Error::new(io::ErrorKind::NotFound, "address resolves to nothing")
+                })
+            })
+            .and_then(|addr| TcpStream::connect_timeout(&addr, timeout))
+            .and_then(|stream| {
+                stream.set_read_timeout(Some(timeout))?;
+                stream.set_write_timeout(Some(timeout))?;
+                Ok(stream)
+            })
+            .map_err(|e| failure(address, e))?;
+
+        let mut line = serde_json::to_vec(&request).map_err(|e| Error::Protocol(e.to_string()))?;
+        line.push(b'\n');
+        (&stream)
+            .write_all(&line)
+            .map_err(|e| failure(address, e))?;
+
+        let mut reply = String::new();
+        BufReader::new(&stream)
+            .read_line(&mut reply)
+            .map_err(|e| failure(address, e))?;
+        if reply.is_empty() {
+            return Err(Error::NodeUnreachable {
+                address: address.to_string(),
+                reason: "connection closed".to_string(),
+            });
+        }
+        serde_json::from_str(&reply)
+            .map_err(|e| Error::Protocol(format!("bad response from {}: {}", address, e)))
+    }
+}
+
+/// Classifies a failed exchange with the node at `address`.
+fn failure(address: &str, e: io::Error) -> Error {
+    match e.kind() {
+        io::ErrorKind::TimedOut | io::ErrorKind::WouldBlock => Error::Timeout {
+            address: address.to_string(),
+        },
+        _ => Error::NodeUnreachable {
+            address: address.to_string(),
+            reason: e.to_string(),
+        },
+    }
+}
+
+/// Answers requests for `node` on `listener` until `stop` is set, running
+/// [`Node::tick`] every `tick_interval` if one is given.
+///
+/// Each connection is answered on its own thread; requests still in progress
+/// when `stop` is set are finished before returning. Connections that cannot
+/// be accepted or answered are passed to `on_error` and the node keeps
+/// serving; only a listener that cannot be set up ends it with an error.
+pub fn serve<I, V, const M: u32>(
+    node: &Node<I, V, M>,
+    listener: &TcpListener,
+    stop: &AtomicBool,
+    tick_interval: Option<Duration>,
+    on_error: impl Fn(Error) + Sync,
+) -> Result<()>
+where
+    I: Identifier + Serialize + DeserializeOwned,
+    V: Clone + Send + fmt::Debug + Serialize + DeserializeOwned + 'static,
+{
+    thread::scope(|scope| {
+        if let Some(interval) = tick_interval {
+            // ticks wait on other nodes, which may be waiting on this one,
+            // so they run apart from the accept loop
+            let mut node = node.clone();
+            scope.spawn(move || {
+                let mut last_tick = Instant::now();
+                while !stop.load(Ordering::Relaxed) {
+                    thread::sleep(POLL_INTERVAL);
+                    if last_tick.elapsed() >= interval {
+                        // see `Node::tick` on why errors are ignored
+                        let _ = node.tick();
+                        last_tick = Instant::now();
+                    }
+                }
+            });
+        }
+        let on_error = &on_error;
+        accept_connections(
+            listener,
+            stop,
+            |stream| {
+                let mut node = node.clone();
+                scope.spawn(move || {
+                    if let Err(e) = answer(&mut node, stream) {
+                        on_error(Error::Io(format!(
+                            "node {}: cannot answer request: {}",
+                            node.id(),
+                            e
+                        )));
+                    }
+                });
+            },
+            |e| {
+                on_error(Error::Io(format!(
+                    "node {}: cannot accept connection: {}",
+                    node.id(),
+                    e
+                )))
+            },
+        )
+    })
+}
+
+/// Passes each connection made to `listener` to `on_connection` until `stop`
+/// is set, polling for both as the listener does not block.
+///
+/// A failed `accept` only loses that connection, or means the process is out
+/// of descriptors for now, so it goes to `on_error` and the loop carries on
+/// after a pause.
+pub(crate) fn accept_connections(
+    listener: &TcpListener,
+    stop: &AtomicBool,
+    mut on_connection: impl FnMut(TcpStream),
+    mut on_error: impl FnMut(io::Error),
+) -> Result<()> {
+    listener
+        .set_nonblocking(true)
+        .io_context(|| "cannot configure listener".to_string())?;
+    while !stop.load(Ordering::Relaxed) {
+        match listener.accept() {
+            Ok((stream, _)) => on_connection(stream),
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => thread::sleep(POLL_INTERVAL),
+            Err(e) => {
+                on_error(e);
+                thread::sleep(POLL_INTERVAL);
+            }
+        }
+    }
+    Ok(())
+}
+
+fn answer<I, V, const M: u32>(node: &mut Node<I, V, M>, stream: TcpStream) -> io::Result<()>
+where
+    I: Identifier + Serialize + DeserializeOwned,
+    V: Clone + Send + fmt::Debug + Serialize + DeserializeOwned + 'static,
+{
+    stream.set_nonblocking(false)?;
+    stream.set_read_timeout(Some(DEFAULT_TIMEOUT))?;
+    let mut line = String::new();
+    BufReader::new(&stream).read_line(&mut line)?;
+    let response = match serde_json::from_str::<Request<I, V>>(&line) {
+        Ok(request) => node.handle(request),
+        Err(e) => Response::Error(Error::Protocol(format!("bad request: {}", e))),
+    };
+    let mut reply = serde_json::to_vec(&response)?;
+    reply.push(b'\n');
+    (&stream).write_all(&reply)?;
+    Ok(())
+}
@@ -1,125 +1,175 @@
-use anyhow::{anyhow, Result};
+use crate::config::{Config, IdCollision, LookupMode};
+use crate::durable::{DurableStorage, DEFAULT_SNAPSHOT_INTERVAL};
+use crate::error::{Error, Result};
+use crate::event::{Event, Observer};
+use crate::hash::{HashFunction, Key};
+use crate::id::Identifier;
+use crate::metrics::{Metrics, MetricsSnapshot};
+use crate::rpc::Transport;
+use crate::storage::{MemoryStorage, Storage};
 use core::fmt;
-use std::{cell::RefCell, collections::HashMap, rc::Rc};
+use serde::{de::DeserializeOwned, Serialize};
+use std::{
+    path::Path,
+    sync::{Arc, Mutex, MutexGuard, PoisonError, RwLock, Weak},
+};
 
-const BITLENGTH: u8 = 8;
+mod remote;
+mod replication;
+mod stabilize;
 
-const MAX: u32 = 2u32.pow(BITLENGTH as u32) - 1;
+pub(crate) use remote::Peer;
+pub use remote::RemoteNode;
 
-type NodeRef = Rc<RefCell<NodeInner>>;
+/// Salted identifiers tried by [`IdCollision::Rehash`] before a join gives up.
+const MAX_REHASHES: u32 = 64;
+
+type NodeRef<I, V, const M: u32> = Arc<NodeState<I, V, M>>;
+pub(crate) type WeakNodeRef<I, V, const M: u32> = Weak<NodeState<I, V, M>>;
+
+/// A Chord node whose identifiers of type `I` live on a ring of `2^M` positions.
+///
+/// Handles are cheap to clone and can be shared between threads. A node's
+/// lock is only held while its own state is read or updated, never while
+/// another node is called, so concurrent operations cannot deadlock. The
+/// joins, leaves and maintenance rounds of one node run one at a time.
 #[derive(Clone)]
-pub struct Node {
-    pub node_inner: NodeRef,
+pub struct Node<I, V, const M: u32> {
+    pub(crate) node_inner: NodeRef<I, V, M>,
+}
+
+/// The state of a node, shared by its handles.
+pub(crate) struct NodeState<I, V, const M: u32> {
+    // apart from the rest so that identifiers, this node's included, can be
+    // read while a node is locked; only changes while the node joins, see
+    // `IdCollision::Rehash`
+    id: RwLock<I>,
+    inner: Mutex<NodeInner<I, V, M>>,
+    // held through joins, leaves and maintenance rounds, which change the
+    // node's place in the ring over several calls to other nodes and so
+    // must not interleave
+    membership: Mutex<()>,
+}
+
+impl<I, V, const M: u32> NodeState<I, V, M> {
+    fn lock_membership(&self) -> MutexGuard<'_, ()> {
+        self.membership
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
 }
 
 #[derive(Clone)]
-struct Finger {
-    start: u8,
-    node: Option<Node>,
+struct Finger<I, V, const M: u32> {
+    start: I,
+    node: Option<Peer<I, V, M>>,
 }
 #[derive(Clone)]
-pub struct FingerTable {
-    node_id: u8,
-    predecessor: Option<Node>,
-    finger_table: Vec<Option<Finger>>,
+pub struct FingerTable<I, V, const M: u32> {
+    node_id: I,
+    predecessor: Option<Peer<I, V, M>>,
+    finger_table: Vec<Finger<I, V, M>>,
+    // the next nodes on the ring, nearest first, used when the successor fails
+    successors: Vec<Peer<I, V, M>>,
 }
 
-#[derive(Clone)]
-pub struct NodeInner {
-    id: u8,
-    finger_table: FingerTable,
-    //key = key identifier/ finger id, aslo the index for fingertable, value = node identifier
-    local_keys: HashMap<u8, Option<u8>>,
-    lookup_info: Vec<String>,
+/// The outcome of looking up a key.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Lookup<I, V> {
+    /// Identifiers of the nodes visited, in order, from the node starting the
+    /// lookup to the owner.
+    pub path: Vec<I>,
+    /// The node responsible for the key.
+    pub owner: I,
+    pub value: Option<V>,
 }
-impl Finger {
-    fn new(start: u8, node: Option<Node>) -> Self {
-        Self { start, node }
+
+impl<I, V> Lookup<I, V> {
+    /// Number of hops between the nodes on the path.
+    pub fn hops(&self) -> usize {
+        self.path.len().saturating_sub(1)
     }
+}
 
-    fn set_node(&mut self, node: Node) {
-        self.node = Some(node);
+pub(crate) struct NodeInner<I, V, const M: u32> {
+    finger_table: FingerTable<I, V, M>,
+    config: Config,
+    //key = key identifier/ finger id, aslo the index for fingertable, value = node identifier
+    local_keys: Box<dyn Storage<I, V>>,
+    // copies of keys owned by the preceding nodes, see `replication`
+    replicas: MemoryStorage<I, V>,
+    // told about every change to this node, see `Node::subscribe`
+    observers: Vec<Arc<dyn Observer<I>>>,
+    metrics: Arc<Metrics>,
+    // finger refreshed by the next call to `fix_fingers`
+    next_finger: u32,
+    alive: bool,
+    // where remote nodes reach this node, and how it reaches them
+    address: Option<String>,
+    transport: Option<Arc<dyn Transport<I, V>>>,
+}
+impl<I: Identifier, V: Clone + Send + 'static, const M: u32> Finger<I, V, M> {
+    fn new(start: I, node: Option<Peer<I, V, M>>) -> Self {
+        Self { start, node }
     }
 }
-impl<'a> FingerTable {
-    fn new(node_id: u8) -> Self {
-        let mut finger_table = Vec::<Option<Finger>>::new();
-        finger_table.push(Some(Finger::new(0, None)));
-        for i in 1..=BITLENGTH {
-            let finger_id = FingerTable::finger_id(node_id, i);
-            finger_table.push(Some(Finger::new(finger_id, None)));
+impl<I: Identifier, V: Clone + Send + 'static, const M: u32> FingerTable<I, V, M> {
+    fn new(node_id: I) -> Self {
+        const {
+            assert!(M >= 1 && M <= I::BITS, "M must fit in the identifier type");
+        }
+        let mut finger_table = Vec::<Finger<I, V, M>>::new();
+        finger_table.push(Finger::new(I::zero(), None));
+        for i in 1..=M {
+            let finger_id = Self::finger_id(node_id, i);
+            finger_table.push(Finger::new(finger_id, None));
         }
 
         Self {
             node_id,
             predecessor: None,
             finger_table,
+            successors: Vec::new(),
         }
     }
 
-    fn set(&mut self, index: u8, successor: Node) {
+    fn set(&mut self, index: u32, successor: Peer<I, V, M>) {
         assert_ne!(index, 0);
-        let offset = 2u32.pow((index - 1) as u32);
-        let power = 2u32.pow(BITLENGTH as u32);
-        let start = (self.node_id as u32 + offset) % power;
-        self.finger_table[index as usize] = Some(Finger::new(start as u8, Some(successor)));
+        let start = Self::finger_id(self.node_id, index);
+        self.finger_table[index as usize] = Finger::new(start, Some(successor));
     }
 
-    fn get(&self, index: u8) -> &Finger {
+    fn get(&self, index: u32) -> &Finger<I, V, M> {
         assert_ne!(index, 0);
-        self.finger_table[index as usize].as_ref().unwrap()
+        &self.finger_table[index as usize]
     }
 
-    fn get_start(&self, index: u8) -> Result<u8> {
-        assert_ne!(index, 0);
-        if let Some(ref finger) = self.finger_table[index as usize] {
-            Ok(finger.start)
-        } else {
-            Err(anyhow!(
-                "Node {}: cannot find start of index {}",
-                self.node_id,
-                index
-            ))
-        }
+    fn get_start(&self, index: u32) -> I {
+        self.get(index).start
     }
 
-    fn get_successor_id(&self) -> u8 {
+    fn get_successor_id(&self) -> I {
         if let Some(ref successor) = self.get(1).node {
-            successor.node_inner.borrow().id
+            successor.id()
         } else {
             self.node_id
         }
     }
 
-    fn get_successor_node(&self) -> Option<Node> {
-        Some(Node::new_inner(Rc::clone(
-            &self.get(1).node.as_ref().unwrap().node_inner,
-        )))
-    }
-
-    fn set_successor(&mut self, node: Node) {
-        //self.successor = Some(node);
-        self.set(1, node);
-    }
-
-    fn set_predecessor(&mut self, predecessor: Option<Node>) {
+    fn set_predecessor(&mut self, predecessor: Option<Peer<I, V, M>>) {
         self.predecessor = predecessor;
     }
 
-    fn get_predecessor_id(&self) -> u8 {
+    fn get_predecessor_id(&self) -> I {
         if let Some(pre) = &self.predecessor {
-            pre.node_inner.borrow().id
+            pre.id()
         } else {
             self.node_id
         }
     }
-    pub fn finger_id(node_id: u8, index: u8) -> u8 {
+    pub fn finger_id(node_id: I, index: u32) -> I {
         assert_ne!(index, 0);
-        let offset: u128 = 2u128.pow((index - 1) as u32);
-        let power: u128 = 2u128.pow(BITLENGTH as u32);
-        let id = (node_id as u128 + offset) % power;
-        //print!("finger id for index {} and node {}: {:#b}\n", index, node_id, id);
-        id as u8
+        node_id.add_mod(I::pow2(index - 1), M)
     }
 
     fn pretty_print(&self) {
@@ -129,419 +179,879 @@ impl<'a> FingerTable {
             self.get_successor_id(),
             self.get_predecessor_id()
         );
+        if !self.successors.is_empty() {
+            let ids: Vec<String> = self.successors.iter().map(|s| s.id().to_string()).collect();
+            println!("Successor list: [{}]", ids.join(", "));
+        }
         println!("FingerTables:");
-        for (i, item) in self.finger_table.iter().enumerate() {
-            if i == 0 {
-                continue;
-            }
-            if let finger = item {
-                let interval_right: u8;
-                if i >= BITLENGTH as usize {
-                    interval_right = self.node_id;
-                } else {
-                    interval_right = self.get_start((i + 1) as u8).unwrap();
-                }
-                println!(
-                    "| k =  {} [ {} , {} )\tsucc. = {}\t|",
-                    i,
-                    finger.as_ref().unwrap().start,
-                    interval_right,
-                    finger
-                        .as_ref()
-                        .unwrap()
-                        .node
-                        .as_ref()
-                        .unwrap()
-                        .node_inner
-                        .borrow()
-                );
-            }
+        for (i, finger) in self.finger_table.iter().enumerate().skip(1) {
+            let interval_right = if i >= M as usize {
+                self.node_id
+            } else {
+                self.get_start((i + 1) as u32)
+            };
+            let succ = match &finger.node {
+                Some(node) => node.to_string(),
+                None => "-".to_string(),
+            };
+            println!(
+                "| k =  {} [ {} , {} )\tsucc. = {}\t|",
+                i, finger.start, interval_right, succ
+            );
         }
         println!("------------------------------");
     }
 }
 
-impl fmt::Display for NodeInner {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "{}", self.id)
-    }
-}
-impl NodeInner {
-    pub fn new(node_id: u8) -> Self {
+impl<I: Identifier, V: Clone + Send + 'static, const M: u32> NodeInner<I, V, M> {
+    pub fn new(node_id: I, config: Config, local_keys: Box<dyn Storage<I, V>>) -> Self {
         Self {
-            id: node_id,
             finger_table: FingerTable::new(node_id),
-            local_keys: HashMap::<u8, Option<u8>>::new(),
-            lookup_info: Vec::new(),
+            config,
+            local_keys,
+            replicas: MemoryStorage::new(),
+            observers: Vec::new(),
+            metrics: Arc::default(),
+            next_finger: 0,
+            alive: false,
+            address: None,
+            transport: None,
         }
     }
 }
 
-impl Node {
-    pub fn new(node_id: u8) -> Self {
+impl<I: Identifier, V: Clone + Send + 'static, const M: u32> Node<I, V, M> {
+    pub fn new(node_id: I) -> Self {
+        Self::with_storage(
+            node_id,
+            HashFunction::default(),
+            Box::new(MemoryStorage::new()),
+        )
+    }
+
+    /// Creates a node whose identifier is the hash of its `host:port` address.
+    pub fn from_address(address: &str, hash_function: HashFunction) -> Self {
+        let node_id = hash_function.hash(address.as_bytes(), M);
+        let node = Self::with_storage(node_id, hash_function, Box::new(MemoryStorage::new()));
+        node.inner().address = Some(address.to_string());
+        node
+    }
+
+    /// Creates a node keeping its keys in `storage` instead of in memory.
+    pub fn with_storage(
+        node_id: I,
+        hash_function: HashFunction,
+        storage: Box<dyn Storage<I, V>>,
+    ) -> Self {
+        let config = Config {
+            hash_function,
+            ..Config::default()
+        };
+        Self::with_config(node_id, config, storage)
+    }
+
+    pub fn with_config(node_id: I, config: Config, storage: Box<dyn Storage<I, V>>) -> Self {
         Self {
-            node_inner: Rc::new(RefCell::new(NodeInner::new(node_id))),
+            node_inner: Arc::new(NodeState {
+                id: RwLock::new(node_id),
+                inner: Mutex::new(NodeInner::new(node_id, config, storage)),
+                membership: Mutex::new(()),
+            }),
+        }
+    }
+
+    /// Creates a node that other processes reach at `address` and that
+    /// reaches them through `transport`; see [`crate::net::serve`].
+    pub fn with_transport(
+        node_id: I,
+        address: String,
+        config: Config,
+        storage: Box<dyn Storage<I, V>>,
+        transport: Arc<dyn Transport<I, V>>,
+    ) -> Self {
+        let node = Self::with_config(node_id, config, storage);
+        {
+            let mut node_inner = node.inner();
+            node_inner.address = Some(address);
+            node_inner.transport = Some(transport);
         }
+        node
     }
-    pub fn new_inner(node_inner: NodeRef) -> Self {
+
+    pub fn id(&self) -> I {
+        *self
+            .node_inner
+            .id
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    pub fn successor_id(&self) -> I {
+        self.inner().finger_table.get_successor_id()
+    }
+
+    /// Identifiers of the nodes in the finger table, from finger 1 to finger `M`.
+    pub fn finger_ids(&self) -> Vec<Option<I>> {
+        let node_inner = self.inner();
+        (1..=M)
+            .map(|i| node_inner.finger_table.get(i).node.as_ref().map(|n| n.id()))
+            .collect()
+    }
+
+    /// Identifiers of the successor list, nearest first.
+    pub fn successor_list(&self) -> Vec<I> {
+        self.inner()
+            .finger_table
+            .successors
+            .iter()
+            .map(|s| s.id())
+            .collect()
+    }
+
+    pub fn predecessor_id(&self) -> Option<I> {
+        self.predecessor().map(|p| p.id())
+    }
+
+    pub fn config(&self) -> Config {
+        self.inner().config.clone()
+    }
+
+    /// Reports every later change to this node to `observer`; see [`Event`].
+    pub fn subscribe(&self, observer: Arc<dyn Observer<I>>) {
+        self.inner().observers.push(observer);
+    }
+
+    /// The node's counters as they stand; see [`MetricsSnapshot`].
+    pub fn metrics(&self) -> MetricsSnapshot {
+        let node_inner = self.inner();
+        node_inner.metrics.snapshot(node_inner.local_keys.len())
+    }
+
+    /// Whether the node is part of a ring: it has joined, and has not left
+    /// or crashed since.
+    pub fn is_alive(&self) -> bool {
+        self.inner().alive
+    }
+    pub(crate) fn new_inner(node_inner: NodeRef<I, V, M>) -> Self {
         Self { node_inner }
     }
 
-    pub fn join(&mut self, node: Option<Node>) -> Result<()> {
+    /// Locks this node's state.
+    ///
+    /// A panic in another thread leaves the state as consistent as an
+    /// abrupt failure would, so a poisoned lock is used as is.
+    fn inner(&self) -> MutexGuard<'_, NodeInner<I, V, M>> {
+        self.node_inner
+            .inner
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn not_joined(&self) -> Error {
+        Error::NotJoined {
+            node: self.id().to_string(),
+        }
+    }
+
+    /// Checks that `id` is a position of this node's ring.
+    fn check_id(id: I) -> Result<()> {
+        if id.fits(M) {
+            Ok(())
+        } else {
+            Err(Error::IdOutOfRange {
+                id: id.to_string(),
+                bits: M,
+            })
+        }
+    }
+
+    /// This node as a peer of itself.
+    fn as_peer(&self) -> Peer<I, V, M> {
+        Peer::Local(self.clone())
+    }
+
+    /// Hands `event` to the observers, outside the lock so they can call
+    /// back into the node.
+    fn emit(&self, event: Event<I>) {
+        let observers = self.inner().observers.clone();
+        for observer in observers {
+            observer.notify(&event);
+        }
+    }
+
+    fn counters(&self) -> Arc<Metrics> {
+        self.inner().metrics.clone()
+    }
+
+    fn set_finger(&self, index: u32, node: Peer<I, V, M>) {
+        let finger = node.id();
+        let previous = {
+            let mut node_inner = self.inner();
+            let previous = node_inner
+                .finger_table
+                .get(index)
+                .node
+                .as_ref()
+                .map(|f| f.id());
+            node_inner.finger_table.set(index, node);
+            previous
+        };
+        if previous != Some(finger) {
+            Metrics::count(&self.counters().finger_updates, 1);
+            self.emit(Event::FingerUpdated {
+                node: self.id(),
+                index,
+                finger,
+            });
+        }
+    }
+
+    fn set_predecessor(&self, predecessor: Option<Peer<I, V, M>>) {
+        let id = predecessor.as_ref().map(|p| p.id());
+        let previous = {
+            let mut node_inner = self.inner();
+            let previous = node_inner.finger_table.predecessor.as_ref().map(|p| p.id());
+            node_inner.finger_table.set_predecessor(predecessor);
+            previous
+        };
+        if previous != id {
+            self.emit(Event::PredecessorChanged {
+                node: self.id(),
+                predecessor: id,
+            });
+        }
+    }
+
+    /// Joins the ring through `node`, or starts a new ring if `None`.
+    ///
+    /// A node that left or crashed may join again; it starts over from the
+    /// keys it still holds, handing those it no longer owns to their owners.
+    /// A node still part of a ring has to leave it first.
+    ///
+    /// If another node already has this node's identifier, the join fails or
+    /// the node takes a new identifier, as set by [`Config::id_collision`].
+    pub fn join(&mut self, node: Option<Node<I, V, M>>) -> Result<()> {
+        self.join_peer(node.map(Peer::Local))
+    }
+
+    fn join_peer(&mut self, node: Option<Peer<I, V, M>>) -> Result<()> {
+        let state = Arc::clone(&self.node_inner);
+        let _membership = state.lock_membership();
+        Self::check_id(self.id())?;
+        self.reset()?;
         if let Some(n) = node {
-            self.init_finger_table(n.clone())?;
+            self.claim_id(&n)?;
+            self.init_finger_table(n)?;
+            // others only reach the node once its fingers are in place
+            self.inner().alive = true;
             self.update_others()?;
-            self.transfer_keys();
-            Ok(())
+            self.update_successor_list()?;
+            self.transfer_keys()?;
+            self.hand_off_foreign_keys()?;
         // first node to join the chord
         } else {
-            for i in 1..=BITLENGTH {
-                self.node_inner
-                    .borrow_mut()
-                    .finger_table
-                    .set(i, Self::new_inner(Rc::clone(&self.node_inner)));
+            self.inner().alive = true;
+            for i in 1..=M {
+                self.set_finger(i, self.as_peer());
+            }
+            self.set_predecessor(Some(self.as_peer()));
+        }
+        self.emit(Event::NodeJoined { node: self.id() });
+        Ok(())
+    }
+
+    /// Checks through `node` that no other node of the ring has this node's
+    /// identifier, first taking a new one if the configuration allows it.
+    ///
+    /// Only nodes already in the ring are seen, so two nodes joining with the
+    /// same identifier at the same time both succeed.
+    fn claim_id(&mut self, node: &Peer<I, V, M>) -> Result<()> {
+        let owner = node.find_successor(self.id())?;
+        if owner.id() != self.id() || owner.is_node(self) {
+            return Ok(());
+        }
+        let (collision, hash_function, seed) = {
+            let node_inner = self.inner();
+            let seed = match &node_inner.address {
+                Some(address) => address.clone(),
+                None => self.id().to_string(),
+            };
+            (
+                node_inner.config.id_collision,
+                node_inner.config.hash_function,
+                seed,
+            )
+        };
+        if collision == IdCollision::Rehash {
+            for salt in 1..=MAX_REHASHES {
+                let id = hash_function.hash(format!("{}#{}", seed, salt).as_bytes(), M);
+                if node.find_successor(id)?.id() != id {
+                    self.set_id(id);
+                    return Ok(());
+                }
             }
-            self.node_inner
-                .borrow_mut()
-                .finger_table
-                .set_predecessor(Some(Self::new_inner(Rc::clone(&self.node_inner))));
-            Ok(())
         }
+        Err(Error::DuplicateId {
+            id: self.id().to_string(),
+        })
     }
 
-    pub fn init_finger_table(&mut self, node: Node) -> Result<()> {
-        let node_successor =
-            node.find_successor(self.node_inner.borrow().finger_table.get_start(1)?)?;
-        self.node_inner
-            .borrow_mut()
-            .finger_table
-            .set_successor(node_successor);
+    /// Forgets the ring this node was part of, if any, before it joins one,
+    /// failing if the node is still part of it.
+    ///
+    /// The node stays down until its new routing state is in place.
+    fn reset(&self) -> Result<()> {
+        let mut node_inner = self.inner();
+        if node_inner.alive {
+            return Err(Error::AlreadyJoined {
+                node: self.id().to_string(),
+            });
+        }
+        node_inner.finger_table = FingerTable::new(self.id());
+        node_inner.next_finger = 0;
+        // replicas of a former ring are stale; maintenance copies new ones
+        node_inner.replicas = MemoryStorage::new();
+        Ok(())
+    }
 
-        let predecessor = self.successor()?.predecessor();
+    /// Moves this node, which is not part of a ring yet, to identifier `id`.
+    fn set_id(&mut self, id: I) {
+        *self
+            .node_inner
+            .id
+            .write()
+            .unwrap_or_else(PoisonError::into_inner) = id;
+        self.inner().finger_table = FingerTable::new(id);
+    }
 
-        self.node_inner
-            .borrow_mut()
-            .finger_table
-            .set_predecessor(predecessor);
+    fn init_finger_table(&mut self, node: Peer<I, V, M>) -> Result<()> {
+        let start = self.inner().finger_table.get_start(1);
+        let node_successor = node.find_successor(start)?;
+        self.set_finger(1, node_successor);
 
-        self.successor()?
-            .node_inner
-            .borrow_mut()
-            .finger_table
-            .set_predecessor(Some(Self::new_inner(Rc::clone(&self.node_inner))));
+        let predecessor = self.successor()?.predecessor()?;
 
-        for i in 1..=BITLENGTH - 1 {
-            let self_id = self.node_inner.borrow().id;
-            let node_inner = &mut self.node_inner.borrow_mut();
-            let finger = node_inner.finger_table.get(i + 1).clone();
-            let finger_pre = node_inner.finger_table.get(i).clone();
-            // if (finger[i + 1].start belongs [n; finger[i].node))
-            if finger_pre.node.is_some()
-                && self.e_is_between_ring(
-                    finger.start,
-                    self_id,
-                    finger_pre.node.as_ref().unwrap().node_inner.borrow().id,
+        self.set_predecessor(predecessor);
+
+        self.successor()?.set_predecessor(Some(&self.as_peer()))?;
+
+        let predecessor_id = self.predecessor_id();
+        for i in 1..=M - 1 {
+            let self_id = self.id();
+            let (finger, finger_pre) = {
+                let node_inner = self.inner();
+                (
+                    node_inner.finger_table.get(i + 1).clone(),
+                    node_inner.finger_table.get(i).clone(),
                 )
+            };
+            // The other nodes cannot answer for the starts this node now
+            // owns, as none of them points to it yet.
+            let owned =
+                predecessor_id.is_some_and(|p| self.is_between_ring_e(finger.start, p, self_id));
+            // if (finger[i + 1].start belongs [n; finger[i].node))
+            let node = match finger_pre
+                .node
+                .filter(|pre_node| self.e_is_between_ring(finger.start, self_id, pre_node.id()))
             {
-                node_inner.finger_table.set(i + 1, finger_pre.node.unwrap());
-                //finger.set_node(finger_pre.node.unwrap());
-                //finger.node = finger_pre.node;
-            } else {
-                node_inner
-                    .finger_table
-                    .set(i + 1, node.find_successor(finger.start)?);
-                //finger.set_node(node.find_successor(finger.start)?);
-                //finger.node = Some(node.find_successor(finger.start)?);
-            }
+                _ if owned => self.as_peer(),
+                Some(pre_node) => pre_node,
+                None => node.find_successor(finger.start)?,
+            };
+            self.set_finger(i + 1, node);
         }
         Ok(())
     }
 
     fn update_others(&mut self) -> Result<()> {
-        for i in 1..=BITLENGTH {
-            let offset = 2u8.pow((i - 1) as u32);
-            let prev = Self::decrease(self.node_inner.borrow().id, offset);
-            let mut p = self.find_predecessor(prev)?.clone();
+        let this = self.as_peer();
+        for i in 1..=M {
+            let offset = I::pow2(i - 1);
+            let prev = Self::decrease(self.id(), offset);
+            let mut p = self.find_predecessor(prev)?;
 
-            if prev == p.successor()?.node_inner.borrow().id {
-                p = p.successor()?;
+            let successor = p.successor()?;
+            if prev == successor.id() {
+                p = successor;
             }
 
-            p.update_finger_table(Self::new_inner(Rc::clone(&self.node_inner)), i);
+            // every node that takes us as its i-th finger passes the update
+            // on to its own predecessor
+            while p.update_finger_table(&this, i)? {
+                match p.predecessor()? {
+                    Some(pre) => p = pre,
+                    None => break,
+                }
+            }
         }
         Ok(())
     }
 
-    fn update_finger_table(&mut self, node: Node, index: u8) {
+    /// Makes `node` the finger at `index` if it precedes the current one and
+    /// returns whether it did, in which case the predecessor is updated next.
+    fn update_finger_table(&self, node: &Peer<I, V, M>, index: u32) -> bool {
         assert_ne!(index, 0);
-        let n_id = self.node_inner.borrow().id;
-        let s_id = node.node_inner.borrow().id;
+        let n_id = self.id();
+        let s_id = node.id();
 
         if s_id != n_id {
+            // an empty finger is filled by any node
             let f_id = self
-                .node_inner
-                .borrow()
+                .inner()
                 .finger_table
                 .get(index)
                 .node
                 .as_ref()
-                .unwrap()
-                .node_inner
-                .borrow()
-                .id;
+                .map_or(n_id, |f| f.id());
             if self.e_is_between_ring(s_id, n_id, f_id) {
-                self.node_inner
-                    .borrow_mut()
-                    .finger_table
-                    .set(index, node.clone());
-                //self.node_inner.borrow().finger_table.get(index).node = Some(node.clone());
-                let predecessor = self.predecessor();
-                if let Some(mut pre) = predecessor {
-                    pre.update_finger_table(node.clone(), index);
-                }
+                self.set_finger(index, node.clone());
+                return true;
             }
         }
+        false
     }
 
-    fn update_finger_table_leave(&mut self, node: Node, index: u8, leav_id: u8) {
-        let n_id = self.node_inner.borrow().id;
-        let s_id = node.node_inner.borrow().id;
-
-        if s_id != n_id {
-            let f_id = self
-                .node_inner
-                .borrow()
+    fn update_finger_table_leave(&self, node: &Peer<I, V, M>, index: u32, leav_id: I) -> bool {
+        let n_id = self.id();
+        // A node leaving a ring of its own has nobody to be replaced by.
+        let leaving = node.id() != leav_id
+            && self
+                .inner()
                 .finger_table
                 .get(index)
                 .node
                 .as_ref()
-                .unwrap()
-                .node_inner
-                .borrow()
-                .id;
-            if f_id == leav_id {
-                self.node_inner
-                    .borrow_mut()
-                    .finger_table
-                    .set(index, node.clone());
-                //self.node_inner.borrow().finger_table.get(index).node = Some(node.clone());
-                let predecessor = self.predecessor();
-                if let Some(mut pre) = predecessor {
-                    pre.update_finger_table_leave(node.clone(), index, leav_id);
-                }
-            }
+                .is_some_and(|f| f.id() == leav_id);
+        if leaving {
+            // The leaving node's successor may be this node itself.
+            let node = if node.id() == n_id {
+                self.as_peer()
+            } else {
+                node.clone()
+            };
+            self.set_finger(index, node);
         }
+        leaving
     }
 
     pub fn leave(&mut self) -> Result<()> {
+        let state = Arc::clone(&self.node_inner);
+        let _membership = state.lock_membership();
+        if !self.is_alive() {
+            return Err(self.not_joined());
+        }
         let successor = self.successor()?;
-        let predecessor: Option<Node> = self.predecessor();
+        let predecessor = self.predecessor();
 
-        successor
-            .node_inner
-            .borrow_mut()
-            .finger_table
-            .set_predecessor(predecessor);
+        successor.set_predecessor(predecessor.as_ref())?;
 
-        self.predecessor()
-            .unwrap()
-            .node_inner
-            .borrow_mut()
-            .finger_table
-            .set_successor(successor);
+        if let Some(predecessor) = predecessor {
+            predecessor.set_successor(&successor)?;
+        }
 
-        self.transfer_keys_leave();
+        self.transfer_keys_leave()?;
         self.update_others_leave()?;
+        self.inner().alive = false;
+        self.emit(Event::NodeLeft { node: self.id() });
         Ok(())
     }
 
+    /// Simulates an abrupt failure: the node stops answering without
+    /// rewiring its neighbours or handing off its keys.
+    ///
+    /// Survivors notice through their successor lists and periodic
+    /// maintenance, see [`Node::tick`].
+    pub fn crash(&mut self) {
+        self.inner().alive = false;
+        self.emit(Event::NodeCrashed { node: self.id() });
+    }
+
     fn update_others_leave(&self) -> Result<()> {
-        let leave_id = self.node_inner.borrow().id;
-        for i in 1..=BITLENGTH {
-            let offset = 2u8.pow((i - 1) as u32);
-            let prev = Self::decrease(self.node_inner.borrow().id, offset);
-            let mut p = self.find_predecessor(prev)?.clone();
-            p.update_finger_table_leave(self.successor()?, i, leave_id);
+        let leave_id = self.id();
+        let successor = self.successor()?;
+        for i in 1..=M {
+            let offset = I::pow2(i - 1);
+            let prev = Self::decrease(self.id(), offset);
+            let mut p = self.find_predecessor(prev)?;
+            while p.update_finger_table_leave(&successor, i, leave_id)? {
+                match p.predecessor()? {
+                    Some(pre) => p = pre,
+                    None => break,
+                }
+            }
         }
         Ok(())
     }
 
     pub fn pretty_print(&self) {
-        self.node_inner.borrow().finger_table.pretty_print();
+        self.inner().finger_table.pretty_print();
     }
 
-    pub fn print_keys(&self) {
-        let id = self.node_inner.borrow().id;
-        let key_len = self.node_inner.borrow().local_keys.len();
-        println!("----------Node id:{}----------", id);
-        print!("{{");
-        for (i, (k, v)) in self.node_inner.borrow().local_keys.iter().enumerate() {
-            let val: String;
-            if v.is_none() {
-                val = "None".to_string();
-            } else {
-                val = v.unwrap().to_string();
-            }
-            if i >= (key_len - 1) {
-                print!("{}: {}", k, val);
-            } else {
-                print!("{}: {}, ", k, val);
-            }
-        }
-        println!("}}");
+    /// The keys this node owns, in ring order starting after its identifier.
+    pub fn keys(&self) -> Vec<(Key<I>, V)> {
+        let id = self.id();
+        self.inner().local_keys.range(id, id)
     }
 
-    pub fn print_lookup_results(&self) {
-        let id = self.node_inner.borrow().id;
-        println!("----------Node id:{}----------", id);
-        for result in self.node_inner.borrow().lookup_info.iter() {
-            println!("{}", result);
-        }
-        println!("------------------------------");
+    pub fn insert(&mut self, key: I, value: V) -> Result<()> {
+        self.insert_entry(Key::from_id(key), value)
     }
 
-    pub fn find(&self, key: u8) -> Option<u8> {
-        let successor = self.find_successor(key).unwrap();
-        let successor_id = successor.node_inner.borrow().id;
-        let self_id = self.node_inner.borrow().id;
-        if successor.node_inner.borrow().local_keys.contains_key(&key) {
-            let value = successor.node_inner.borrow().local_keys[&key];
-            let v: String;
-            if value.is_none() {
-                v = "None".to_string();
-            } else {
-                v = value.unwrap().to_string();
-            }
+    /// Stores `value` under the identifier `key` hashes to, keeping `key` itself alongside it.
+    pub fn insert_key(&mut self, key: impl AsRef<[u8]>, value: V) -> Result<()> {
+        let key = self.hash_key(key.as_ref());
+        self.insert_entry(key, value)
+    }
 
-            if successor_id == self_id {
-                self.node_inner.borrow_mut().lookup_info.push(format!(
-                    "Look-up result of key {} from node {} with path [{}] value is {}",
-                    key, self_id, self_id, v
-                ));
-            } else {
-                self.node_inner.borrow_mut().lookup_info.push(format!(
-                    "Look-up result of key {} from node {} with path [{},{}] value is {}",
-                    key, self_id, self_id, successor_id, v
-                ));
-            }
-            value
-        } else {
-            None
+    fn insert_entry(&mut self, key: Key<I>, value: V) -> Result<()> {
+        Self::check_id(key.id)?;
+        let successor = self.find_successor(key.id)?;
+        self.put_replicas(&successor, &key, &value)?;
+        successor.put(key, value)
+    }
+
+    pub fn remove(&mut self, key: I) -> Result<()> {
+        self.remove_entry(&Key::from_id(key))
+    }
+
+    pub fn remove_key(&mut self, key: impl AsRef<[u8]>) -> Result<()> {
+        let key = self.hash_key(key.as_ref());
+        self.remove_entry(&key)
+    }
+
+    fn remove_entry(&mut self, key: &Key<I>) -> Result<()> {
+        Self::check_id(key.id)?;
+        let successor = self.find_successor(key.id)?;
+        let found = successor.delete(key)?;
+        // stale replicas may be left over even if the owner lacks the key
+        self.delete_replicas(&successor, key)?;
+        if !found {
+            return Err(Error::KeyNotFound {
+                key: key.to_string(),
+            });
         }
+        Ok(())
     }
 
-    pub fn insert(&mut self, key: u8, value: Option<u8>) {
-        let successor = self.find_successor(key).unwrap();
-        successor
-            .node_inner
-            .borrow_mut()
+    /// The key that [`Node::insert_key`] and the other byte-key operations
+    /// store or look up for `key`.
+    pub fn hash_key(&self, key: impl AsRef<[u8]>) -> Key<I> {
+        Key::from_name(key.as_ref(), self.inner().config.hash_function, M)
+    }
+
+    /// Looks `key` up among the keys this node owns or replicates.
+    fn get_entry(&self, key: &Key<I>) -> Option<V> {
+        let node_inner = self.inner();
+        Metrics::count(&node_inner.metrics.lookups_served, 1);
+        // the owner may have inherited the key as a replica of a failed node
+        // that its maintenance has not promoted yet
+        node_inner
             .local_keys
-            .insert(key, value);
-    }
-
-    fn remove(&mut self, key: u8) {
-        let successor = self.find_successor(key).unwrap();
-        successor.node_inner.borrow_mut().local_keys.remove(&key);
-    }
-
-    fn transfer_keys(&mut self) {
-        let successor = self.successor().unwrap();
-        let mut del_keys = Vec::<u8>::new();
-        let mut migrations = Vec::<String>::new();
-        let successor_id = successor.node_inner.borrow().id;
-        let self_id = self.node_inner.borrow().id;
-        for (k, v) in successor.node_inner.borrow().local_keys.iter() {
-            let node = self.find_successor(*k).unwrap();
-            // transfer key from successor to current node
-            if node.node_inner.borrow().id == self.node_inner.borrow().id {
-                migrations.push(format!(
-                    "migrate key {} from node {} to node {}",
-                    k, successor_id, self_id
-                ));
-                self.node_inner.borrow_mut().local_keys.insert(*k, *v);
-                del_keys.push(*k);
+            .get(key)
+            .or_else(|| node_inner.replicas.get(key))
+    }
+
+    fn transfer_keys(&mut self) -> Result<()> {
+        let successor = self.successor()?;
+        let successor_id = successor.id();
+        let self_id = self.id();
+        let predecessor_id = self.inner().finger_table.get_predecessor_id();
+        // transfer keys in (predecessor, self] from successor to current node,
+        // copying before deleting so a crash in between loses nothing
+        let copied = successor.get_keys(predecessor_id, self_id)?;
+        for (k, v) in copied {
+            self.inner().local_keys.put(k, v)?;
+        }
+        // the values drained are newer than the copies if a put reached the
+        // successor in between
+        let moved = successor.transfer_keys(predecessor_id, self_id)?;
+        let mut migrated = Vec::with_capacity(moved.len());
+        for (k, v) in moved {
+            self.inner().local_keys.put(k.clone(), v)?;
+            migrated.push(k);
+        }
+
+        Metrics::count(&self.counters().keys_migrated_in, migrated.len());
+        for key in migrated {
+            self.emit(Event::KeyMigrated {
+                key,
+                from: successor_id,
+                to: self_id,
+            });
+        }
+        Ok(())
+    }
+
+    fn transfer_keys_leave(&mut self) -> Result<()> {
+        let successor = self.successor()?;
+        let successor_id = successor.id();
+        let self_id = self.id();
+        // the last node of a ring keeps its keys, to have them on rejoining
+        if successor_id == self_id {
+            return Ok(());
+        }
+        let moved = self.inner().local_keys.range(self_id, self_id);
+        for (k, _) in moved {
+            // transfer key from current to successor node
+            if self.move_key(&k, |k, v| successor.put(k, v))? {
+                Metrics::count(&self.counters().keys_migrated_out, 1);
+                self.emit(Event::KeyMigrated {
+                    key: k,
+                    from: self_id,
+                    to: successor_id,
+                });
             }
         }
-        for key in del_keys {
-            successor.node_inner.borrow_mut().local_keys.remove(&key);
+        Ok(())
+    }
+
+    /// Re-inserts keys this node holds but no longer owns, such as keys
+    /// recovered from disk after the ring changed while the node was down.
+    fn hand_off_foreign_keys(&mut self) -> Result<()> {
+        let self_id = self.id();
+        let predecessor_id = self.inner().finger_table.get_predecessor_id();
+        if predecessor_id == self_id {
+            return Ok(());
+        }
+        let foreign = self.inner().local_keys.range(self_id, predecessor_id);
+        let mut node = self.clone();
+        for (k, _) in foreign {
+            self.move_key(&k, |k, v| node.insert_entry(k, v))?;
         }
+        Ok(())
+    }
 
-        if !migrations.is_empty() {
-            println!("******************************");
-            for migration in migrations {
-                println!("{}", migration);
+    /// Hands the entry under `key` to `send`, if this node still holds it,
+    /// and returns whether it did.
+    ///
+    /// The entry is taken out before it is sent, so a value written in the
+    /// meantime stays here instead of being deleted with the one sent. An
+    /// entry that cannot be sent is put back, unless a newer value arrived.
+    fn move_key(&self, key: &Key<I>, send: impl FnOnce(Key<I>, V) -> Result<()>) -> Result<bool> {
+        let Some(value) = self.inner().local_keys.delete(key)? else {
+            return Ok(false);
+        };
+        if let Err(e) = send(key.clone(), value.clone()) {
+            let mut node_inner = self.inner();
+            if node_inner.local_keys.get(key).is_none() {
+                node_inner.local_keys.put(key.clone(), value)?;
             }
+            return Err(e);
         }
+        Ok(true)
     }
 
-    fn transfer_keys_leave(&mut self) {
-        let mut successor = self.successor().unwrap();
-        let mut del_keys = Vec::<u8>::new();
-        let mut migrations = Vec::<String>::new();
-        let successor_id = successor.node_inner.borrow().id;
-        let self_id = self.node_inner.borrow().id;
-        for (k, v) in self.node_inner.borrow().local_keys.iter() {
-            // transfer key from current to successor node
-            successor.insert(*k, *v);
-            del_keys.push(*k);
-            migrations.push(format!(
-                "migrate key {} from node {} to node {}",
-                k, self_id, successor_id
-            ));
-        }
-        for key in del_keys {
-            self.remove(key);
-        }
-        if !migrations.is_empty() {
-            println!("******************************");
-            for migration in migrations {
-                println!("{}", migration);
+    /// Returns the first live node among the successor and the successor
+    /// list, repairing the successor pointer if it had to skip failed nodes.
+    fn successor(&self) -> Result<Peer<I, V, M>> {
+        let self_id = self.id();
+        let (current, candidates) = {
+            let binding = self.inner();
+            let current = binding.finger_table.get(1).node.clone();
+            let mut candidates: Vec<Peer<I, V, M>> = current.iter().cloned().collect();
+            candidates.extend(binding.finger_table.successors.iter().cloned());
+            (current, candidates)
+        };
+        if candidates.is_empty() {
+            return Err(self.not_joined());
+        }
+        let live = candidates
+            .into_iter()
+            .find(|s| s.is_alive() || s.id() == self_id);
+        match live {
+            Some(s) => {
+                if current.is_none_or(|c| c.id() != s.id()) {
+                    self.set_finger(1, s.clone());
+                }
+                Ok(s)
             }
+            None => Err(Error::NoSuccessor {
+                node: self_id.to_string(),
+            }),
         }
     }
 
-    fn successor(&self) -> Result<Node> {
-        let binding = self.node_inner.borrow();
-        let suc = binding.finger_table.get_successor_node();
-        if let Some(s) = suc {
-            Ok(Self::new_inner(Rc::clone(&s.node_inner)))
-        } else {
-            Err(anyhow!(
-                "Node {}: successor is None",
-                self.node_inner.borrow().id
-            ))
+    /// Rebuilds the successor list from the successor's own list.
+    fn update_successor_list(&mut self) -> Result<()> {
+        let successor = self.successor()?;
+        let self_id = self.id();
+        let len = self.inner().config.successor_list_len;
+        let mut successors = vec![successor.clone()];
+        if successor.id() != self_id {
+            let next = successor.successor_list()?;
+            successors.extend(
+                next.into_iter()
+                    .filter(|s| s.is_alive())
+                    .take_while(|s| s.id() != self_id),
+            );
         }
+        successors.truncate(len.max(1));
+        self.inner().finger_table.successors = successors;
+        Ok(())
     }
 
-    fn predecessor(&self) -> Option<Node> {
-        self.node_inner.borrow().finger_table.predecessor.clone()
+    fn predecessor(&self) -> Option<Peer<I, V, M>> {
+        self.inner().finger_table.predecessor.clone()
     }
 
-    fn find_successor(&self, id: u8) -> Result<Node> {
-        let n = self.find_predecessor(id)?;
-        n.successor()
+    fn find_successor(&self, id: I) -> Result<Peer<I, V, M>> {
+        self.find_successor_path(id).map(|(successor, _)| successor)
     }
 
-    fn decrease(value: u8, size: u8) -> u8 {
-        if size <= value {
-            value - size
+    /// Like [`Node::find_successor`], also returning the identifiers of the
+    /// nodes visited, in order, from this node to the successor.
+    fn find_successor_path(&self, id: I) -> Result<(Peer<I, V, M>, Vec<I>)> {
+        if !self.is_alive() {
+            return Err(self.not_joined());
+        }
+        let mode = self.inner().config.lookup_mode;
+        let (successor, path) = if mode == LookupMode::Recursive {
+            self.route(id, Vec::new())?
         } else {
-            (MAX - (size - value) as u32) as u8
+            let mut path = Vec::new();
+            let (n, successor) = self.find_predecessor_path(id, &mut path)?;
+            if successor.id() != n.id() {
+                path.push(successor.id());
+            }
+            (successor, path)
+        };
+        self.counters().record_hops(path.len().saturating_sub(1));
+        Ok((successor, path))
+    }
+
+    /// Resolves the successor of `id` recursively, for a query that has
+    /// passed through the nodes on `path`: answers from this node's own
+    /// state if its successor is the one, and otherwise forwards the query
+    /// to the closest preceding node, starting over if that node fails.
+    fn route(&self, id: I, mut path: Vec<I>) -> Result<(Peer<I, V, M>, Vec<I>)> {
+        let self_id = self.id();
+        let looped = || Error::RoutingLoop {
+            id: id.to_string(),
+            node: self_id.to_string(),
+        };
+        if path.contains(&self_id) {
+            return Err(looped());
+        }
+        path.push(self_id);
+        let hop_timeout = self.inner().config.hop_timeout;
+        loop {
+            let successor = self.successor()?;
+            if self.is_between_ring_e(id, self_id, successor.id()) {
+                if successor.id() != self_id {
+                    path.push(successor.id());
+                }
+                return Ok((successor, path));
+            }
+            let next = self.closest_preceding_node(id)?;
+            if next.id() == self_id {
+                return Err(looped());
+            }
+            match next.with_timeout(hop_timeout).route(id, path.clone()) {
+                // a hop that does not answer is marked as failed, so the
+                // next attempt avoids it
+                Err(_) if !next.is_alive() => continue,
+                result => return result,
+            }
         }
     }
-    fn find_predecessor(&self, id: u8) -> Result<Node> {
-        let mut n = self.clone();
-        while !self.is_between_ring_e(
-            id,
-            n.node_inner.borrow().id,
-            n.successor()?.node_inner.borrow().id,
-        ) {
-            n = n.closest_preceding_node(id)?;
+
+    fn decrease(value: I, size: I) -> I {
+        value.sub_mod(size, M)
+    }
+
+    fn find_predecessor(&self, id: I) -> Result<Peer<I, V, M>> {
+        self.find_predecessor_path(id, &mut Vec::new())
+            .map(|(predecessor, _)| predecessor)
+    }
+
+    /// Walks the fingers towards the predecessor of `id`, appending every
+    /// node visited to `path`, and returns it along with its successor.
+    ///
+    /// A hop that does not answer is passed over: the walk goes on from the
+    /// hop before it, through the next closest node that one knows of.
+    fn find_predecessor_path(
+        &self,
+        id: I,
+        path: &mut Vec<I>,
+    ) -> Result<(Peer<I, V, M>, Peer<I, V, M>)> {
+        let mut n = self.as_peer();
+        // the last hop that answered, and the hops that did not
+        let mut last: Option<Peer<I, V, M>> = None;
+        let mut failed: Vec<I> = Vec::new();
+        loop {
+            let n_id = n.id();
+            let successor = match Self::live_successor(&n, &failed) {
+                Ok(successor) => successor,
+                Err(e) => {
+                    let Some(from) = last.clone() else {
+                        return Err(e);
+                    };
+                    failed.push(n_id);
+                    n = self.next_hop(&from, id, &failed)?.ok_or(e)?;
+                    continue;
+                }
+            };
+            let revisited = path.contains(&n_id);
+            if !revisited {
+                path.push(n_id);
+            }
+            if self.is_between_ring_e(id, n_id, successor.id()) {
+                return Ok((n, successor));
+            }
+            let looped = || Error::RoutingLoop {
+                id: id.to_string(),
+                node: n_id.to_string(),
+            };
+            // no progress means the routing state is inconsistent
+            if revisited {
+                return Err(looped());
+            }
+            let next = self.next_hop(&n, id, &failed)?.ok_or_else(looped)?;
+            last = Some(n);
+            n = next;
+        }
+    }
+
+    /// The successor of `n`, or the first node of its successor list if
+    /// the successor is among the `failed` hops of a lookup.
+    fn live_successor(n: &Peer<I, V, M>, failed: &[I]) -> Result<Peer<I, V, M>> {
+        let successor = n.successor()?;
+        if !failed.contains(&successor.id()) {
+            return Ok(successor);
+        }
+        n.successor_list()?
+            .into_iter()
+            .find(|s| !failed.contains(&s.id()))
+            .ok_or_else(|| Error::NoSuccessor {
+                node: n.id().to_string(),
+            })
+    }
+
+    /// The node `n` knows of that most closely precedes `id`, passing over
+    /// the `failed` hops of a lookup: through a node before a failed one,
+    /// or else one past it in the successor list of `n`.
+    fn next_hop(&self, n: &Peer<I, V, M>, id: I, failed: &[I]) -> Result<Option<Peer<I, V, M>>> {
+        let hop_timeout = self.inner().config.hop_timeout;
+        let n_id = n.id();
+        let mut next = n.closest_preceding_node(id)?;
+        if failed.contains(&next.id()) {
+            next = n.closest_preceding_node(next.id())?;
+        }
+        if failed.contains(&next.id()) || next.id() == n_id {
+            let past = n
+                .successor_list()?
+                .into_iter()
+                .filter(|s| !failed.contains(&s.id()) && self.between(s.id(), n_id, id))
+                .last();
+            match past {
+                Some(past) => next = past,
+                None => return Ok(None),
+            }
         }
-        Ok(n)
+        Ok(Some(next.with_timeout(hop_timeout)))
     }
 
-    fn is_between_ring_e(&self, id: u8, node1: u8, node2: u8) -> bool {
+    fn is_between_ring_e(&self, id: I, node1: I, node2: I) -> bool {
         if id == node2 {
             true
         } else {
@@ -549,7 +1059,7 @@ impl Node {
         }
     }
 
-    fn e_is_between_ring(&self, id: u8, node1: u8, node2: u8) -> bool {
+    fn e_is_between_ring(&self, id: I, node1: I, node2: I) -> bool {
         if id == node1 {
             true
         } else {
@@ -557,39 +1067,125 @@ impl Node {
         }
     }
 
-    fn between(&self, mut id: u8, mut node1: u8, mut node2: u8) -> bool {
+    fn between(&self, id: I, node1: I, node2: I) -> bool {
         if node1 == node2 {
-            return true;
-        } else if node1 > node2 {
-            let shift = MAX as u8 - node1;
-            node1 = 0;
-            node2 = (node2 + shift) % MAX as u8;
-            id = ((id as u32 + shift as u32) % MAX) as u8;
-        }
-        node1 < id && id < node2 && node1 < node2
-    }
-
-    fn closest_preceding_node(&self, id: u8) -> Result<Node> {
-        for i in (1..=BITLENGTH).rev() {
-            let node_inner = self.node_inner.borrow();
-            if node_inner.finger_table.get(i).node.is_some()
-                && self.between(
-                    node_inner
-                        .finger_table
-                        .get(i)
-                        .node
-                        .as_ref()
-                        .unwrap()
-                        .node_inner
-                        .borrow()
-                        .id,
-                    node_inner.id,
-                    id,
-                )
-            {
-                return Ok(node_inner.finger_table.get(i).node.clone().unwrap());
+            true
+        } else if node1 < node2 {
+            node1 < id && id < node2
+        } else {
+            // the interval wraps around zero
+            node1 < id || id < node2
+        }
+    }
+
+    /// Returns the live finger or successor-list entry that most closely precedes `id`.
+    fn closest_preceding_node(&self, id: I) -> Result<Peer<I, V, M>> {
+        let self_id = self.id();
+        // checking liveness may lock other nodes, so it happens after our
+        // own lock is released
+        let candidates: Vec<Peer<I, V, M>> = {
+            let node_inner = self.inner();
+            let fingers = (1..=M)
+                .rev()
+                .filter_map(|i| node_inner.finger_table.get(i).node.clone());
+            fingers
+                .chain(node_inner.finger_table.successors.iter().cloned())
+                .filter(|n| self.between(n.id(), self_id, id))
+                .collect()
+        };
+        let closest = candidates
+            .into_iter()
+            .filter(|n| n.is_alive())
+            .max_by_key(|n| n.id().sub_mod(self_id, M));
+        Ok(closest.unwrap_or_else(|| self.as_peer()))
+    }
+}
+
+impl<I, V, const M: u32> Node<I, V, M>
+where
+    I: Identifier + Serialize + DeserializeOwned,
+    V: Clone + Send + Serialize + DeserializeOwned + 'static,
+{
+    /// Opens a node whose keys are persisted under `data_dir`.
+    ///
+    /// Keys written before a crash or shutdown are recovered from disk; the
+    /// node then rejoins the ring through [`Node::join`], which hands any
+    /// recovered keys it no longer owns to their current owners.
+    pub fn open(
+        node_id: I,
+        hash_function: HashFunction,
+        data_dir: impl AsRef<Path>,
+    ) -> Result<Self> {
+        let storage = DurableStorage::open(data_dir, DEFAULT_SNAPSHOT_INTERVAL)?;
+        Ok(Self::with_storage(
+            node_id,
+            hash_function,
+            Box::new(storage),
+        ))
+    }
+}
+
+impl<I: Identifier, V: Clone + Send + fmt::Debug + 'static, const M: u32> Node<I, V, M> {
+    pub fn print_keys(&self) {
+        let id = self.id();
+        let keys = self.inner().local_keys.range(id, id);
+        let key_len = keys.len();
+        println!("----------Node id:{}----------", id);
+        print!("{{");
+        for (i, (k, v)) in keys.iter().enumerate() {
+            if i >= (key_len - 1) {
+                print!("{}: {:?}", k, v);
+            } else {
+                print!("{}: {:?}, ", k, v);
             }
         }
-        Ok(Self::new_inner(Rc::clone(&self.node_inner)))
+        println!("}}");
+    }
+
+    pub fn find(&self, key: I) -> Result<Option<V>> {
+        self.lookup(key).map(|lookup| lookup.value)
+    }
+
+    /// Looks up a key by the bytes it is hashed from.
+    pub fn find_key(&self, key: impl AsRef<[u8]>) -> Result<Option<V>> {
+        self.lookup_key(key).map(|lookup| lookup.value)
+    }
+
+    /// Looks up a key, reporting the path the lookup took.
+    pub fn lookup(&self, key: I) -> Result<Lookup<I, V>> {
+        self.lookup_entry(&Key::from_id(key))
+    }
+
+    /// Like [`Node::lookup`], for a key given by the bytes it is hashed from.
+    pub fn lookup_key(&self, key: impl AsRef<[u8]>) -> Result<Lookup<I, V>> {
+        self.lookup_entry(&self.hash_key(key.as_ref()))
+    }
+
+    fn lookup_entry(&self, key: &Key<I>) -> Result<Lookup<I, V>> {
+        Self::check_id(key.id)?;
+        Metrics::count(&self.counters().lookups_initiated, 1);
+        let (owner, mut path) = self.find_successor_path(key.id)?;
+        let (owner, value) = match owner.get(key) {
+            Ok(value) => (owner, value),
+            // the owner failed before the ring noticed; its replica holders
+            // answer for it
+            Err(e) => {
+                let (holder, value) = self.get_replica(key, &owner, e)?;
+                path.push(holder.id());
+                (holder, value)
+            }
+        };
+        self.emit(Event::LookupCompleted {
+            node: self.id(),
+            key: key.clone(),
+            path: path.clone(),
+            owner: owner.id(),
+            found: value.is_some(),
+        });
+        Ok(Lookup {
+            path,
+            owner: owner.id(),
+            value,
+        })
     }
 }
@@ -0,0 +1,563 @@
+//! Nodes reached through messages.
+//!
+//! Routing state refers to other nodes through [`Peer`], which is either a
+//! handle into the same process or a [`RemoteNode`] reached through a
+//! [`Transport`]. A node answers the requests of remote peers in
+//! [`Node::handle`]. A ring whose nodes only know each other by address,
+//! over TCP or [`crate::rpc::MemoryTransport`], runs entirely on messages.
+//!
+//! Recursive steps of the protocol, such as [`Node::update_finger_table`],
+//! are driven by the node that started them rather than forwarded from node
+//! to node, so no request waits on a chain of further requests. Recursive
+//! lookups, see [`crate::config::LookupMode`], are the one exception.
+
+use super::{Node, WeakNodeRef};
+use crate::error::{Error, Result};
+use crate::hash::Key;
+use crate::id::Identifier;
+use crate::metrics::Metrics;
+use crate::rpc::{NodeInfo, Request, Response, Transport};
+use crate::storage::Storage;
+use core::fmt;
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+type Origin<I, V, const M: u32> = (String, WeakNodeRef<I, V, M>);
+
+/// Another node as seen from this one.
+#[derive(Clone)]
+pub(crate) enum Peer<I, V, const M: u32> {
+    Local(Node<I, V, M>),
+    Remote(RemoteNode<I, V, M>),
+}
+
+/// A handle to a node in another process, offering the same operations as
+/// a local [`Node`].
+#[derive(Clone)]
+pub struct RemoteNode<I, V, const M: u32> {
+    info: NodeInfo<I>,
+    transport: Arc<dyn Transport<I, V>>,
+    // the node this handle was obtained by, whose own address resolves to
+    // its local handle instead of a round trip through the transport
+    origin: Option<Origin<I, V, M>>,
+    // whether the last request reached the node, shared between clones
+    alive: Arc<AtomicBool>,
+    // how long requests may take, if shorter than the transport's default
+    timeout: Option<Duration>,
+    // the counters of the origin node, which sends the requests
+    metrics: Option<Arc<Metrics>>,
+}
+
+impl<I: Identifier, V: Clone + Send + 'static, const M: u32> RemoteNode<I, V, M> {
+    pub fn new(info: NodeInfo<I>, transport: Arc<dyn Transport<I, V>>) -> Self {
+        Self {
+            info,
+            transport,
+            origin: None,
+            alive: Arc::new(AtomicBool::new(true)),
+            timeout: None,
+            metrics: None,
+        }
+    }
+
+    /// Connects to the node listening on `address`, asking it for its identifier.
+    pub fn connect(address: &str, transport: Arc<dyn Transport<I, V>>) -> Result<Self> {
+        let info = transport.call(address, Request::Info)?.into_node()?;
+        Ok(Self::new(info, transport))
+    }
+
+    pub fn id(&self) -> I {
+        self.info.id
+    }
+
+    pub fn address(&self) -> &str {
+        &self.info.address
+    }
+
+    pub fn info(&self) -> &NodeInfo<I> {
+        &self.info
+    }
+
+    /// Whether the node answered the last request sent to it.
+    pub fn is_alive(&self) -> bool {
+        self.alive.load(Ordering::Relaxed)
+    }
+
+    /// Asks the node whether it is still part of the ring.
+    pub fn ping(&self) -> bool {
+        let alive = self
+            .call(Request::Ping)
+            .and_then(|r| r.into_bool())
+            .unwrap_or(false);
+        self.alive.store(alive, Ordering::Relaxed);
+        alive
+    }
+
+    pub fn join(&self, node: Option<&RemoteNode<I, V, M>>) -> Result<()> {
+        self.call(Request::Join(node.map(|n| n.info.clone())))?
+            .into_ok()
+    }
+
+    /// Like [`RemoteNode::join`], but through [`Node::join_stabilized`].
+    pub fn join_stabilized(&self, node: Option<&RemoteNode<I, V, M>>) -> Result<()> {
+        self.call(Request::JoinStabilized(node.map(|n| n.info.clone())))?
+            .into_ok()
+    }
+
+    pub fn leave(&self) -> Result<()> {
+        self.call(Request::Leave)?.into_ok()
+    }
+
+    pub fn tick(&self) -> Result<()> {
+        self.call(Request::Tick)?.into_ok()
+    }
+
+    pub fn insert(&self, key: I, value: V) -> Result<()> {
+        self.call(Request::Insert(Key::from_id(key), value))?
+            .into_ok()
+    }
+
+    pub fn remove(&self, key: I) -> Result<()> {
+        self.call(Request::Remove(Key::from_id(key)))?.into_ok()
+    }
+
+    pub fn find(&self, key: I) -> Result<Option<V>> {
+        self.call(Request::Find(Key::from_id(key)))?.into_value()
+    }
+
+    /// The keys the node owns, in ring order starting after its identifier.
+    pub fn keys(&self) -> Result<Vec<(Key<I>, V)>> {
+        self.call(Request::Keys)?.into_entries()
+    }
+
+    fn call(&self, request: Request<I, V>) -> Result<Response<I, V>> {
+        if let Some(metrics) = &self.metrics {
+            Metrics::count(&metrics.rpcs_sent, 1);
+        }
+        let response = match self.timeout {
+            Some(timeout) => self
+                .transport
+                .call_with_timeout(&self.info.address, request, timeout),
+            None => self.transport.call(&self.info.address, request),
+        };
+        if response.is_err() {
+            self.alive.store(false, Ordering::Relaxed);
+        }
+        response
+    }
+
+    /// Turns a node named in a response into a peer of the origin node.
+    fn peer(&self, info: NodeInfo<I>) -> Peer<I, V, M> {
+        if let Some((address, node_inner)) = &self.origin {
+            if *address == info.address {
+                if let Some(node_inner) = node_inner.upgrade() {
+                    return Peer::Local(Node::new_inner(node_inner));
+                }
+            }
+        }
+        Peer::Remote(Self {
+            info,
+            transport: Arc::clone(&self.transport),
+            origin: self.origin.clone(),
+            alive: Arc::new(AtomicBool::new(true)),
+            timeout: None,
+            metrics: self.metrics.clone(),
+        })
+    }
+}
+
+impl<I: Identifier, V: Clone + Send + 'static, const M: u32> Peer<I, V, M> {
+    pub fn id(&self) -> I {
+        match self {
+            Peer::Local(n) => n.id(),
+            Peer::Remote(r) => r.id(),
+        }
+    }
+
+    /// How remote nodes can reach this peer.
+    pub fn info(&self) -> Result<NodeInfo<I>> {
+        match self {
+            Peer::Local(n) => n.info(),
+            Peer::Remote(r) => Ok(r.info.clone()),
+        }
+    }
+
+    /// Whether the peer is `node` itself.
+    pub(super) fn is_node(&self, node: &Node<I, V, M>) -> bool {
+        matches!(self, Peer::Local(n) if Arc::ptr_eq(&n.node_inner, &node.node_inner))
+    }
+
+    /// Whether the peer is believed to be alive, without contacting it.
+    pub fn is_alive(&self) -> bool {
+        match self {
+            Peer::Local(n) => n.is_alive(),
+            Peer::Remote(r) => r.is_alive(),
+        }
+    }
+
+    /// Checks whether the peer is alive, contacting it if it is remote.
+    pub fn ping(&self) -> bool {
+        match self {
+            Peer::Local(n) => n.is_alive(),
+            Peer::Remote(r) => r.ping(),
+        }
+    }
+
+    pub(super) fn find_successor(&self, id: I) -> Result<Peer<I, V, M>> {
+        match self {
+            Peer::Local(n) => n.find_successor(id),
+            Peer::Remote(r) => Ok(r.peer(r.call(Request::FindSuccessor(id))?.into_node()?)),
+        }
+    }
+
+    /// The same peer, giving up on requests after `timeout`.
+    pub(super) fn with_timeout(&self, timeout: Duration) -> Peer<I, V, M> {
+        match self {
+            Peer::Local(n) => Peer::Local(n.clone()),
+            Peer::Remote(r) => Peer::Remote(RemoteNode {
+                timeout: Some(timeout),
+                ..r.clone()
+            }),
+        }
+    }
+
+    /// Resolves the successor of `id` recursively through this peer; see
+    /// [`Node::route`].
+    pub(super) fn route(&self, id: I, path: Vec<I>) -> Result<(Peer<I, V, M>, Vec<I>)> {
+        match self {
+            Peer::Local(n) => n.route(id, path),
+            Peer::Remote(r) => {
+                let (info, path) = r.call(Request::Route { id, path })?.into_route()?;
+                Ok((r.peer(info), path))
+            }
+        }
+    }
+
+    pub(super) fn successor(&self) -> Result<Peer<I, V, M>> {
+        match self {
+            Peer::Local(n) => n.successor(),
+            Peer::Remote(r) => Ok(r.peer(r.call(Request::GetSuccessor)?.into_node()?)),
+        }
+    }
+
+    pub(super) fn successor_list(&self) -> Result<Vec<Peer<I, V, M>>> {
+        match self {
+            Peer::Local(n) => Ok(n.inner().finger_table.successors.clone()),
+            Peer::Remote(r) => Ok(r
+                .call(Request::GetSuccessorList)?
+                .into_nodes()?
+                .into_iter()
+                .map(|info| r.peer(info))
+                .collect()),
+        }
+    }
+
+    pub(super) fn predecessor(&self) -> Result<Option<Peer<I, V, M>>> {
+        match self {
+            Peer::Local(n) => Ok(n.predecessor()),
+            Peer::Remote(r) => Ok(r
+                .call(Request::GetPredecessor)?
+                .into_maybe_node()?
+                .map(|info| r.peer(info))),
+        }
+    }
+
+    pub(super) fn set_predecessor(&self, predecessor: Option<&Peer<I, V, M>>) -> Result<()> {
+        match self {
+            Peer::Local(n) => {
+                n.set_predecessor(predecessor.cloned());
+                Ok(())
+            }
+            Peer::Remote(r) => {
+                let info = predecessor.map(|p| p.info()).transpose()?;
+                r.call(Request::SetPredecessor(info))?.into_ok()
+            }
+        }
+    }
+
+    pub(super) fn set_successor(&self, successor: &Peer<I, V, M>) -> Result<()> {
+        match self {
+            Peer::Local(n) => {
+                n.set_finger(1, successor.clone());
+                Ok(())
+            }
+            Peer::Remote(r) => r.call(Request::SetSuccessor(successor.info()?))?.into_ok(),
+        }
+    }
+
+    pub(super) fn closest_preceding_node(&self, id: I) -> Result<Peer<I, V, M>> {
+        match self {
+            Peer::Local(n) => n.closest_preceding_node(id),
+            Peer::Remote(r) => Ok(r.peer(r.call(Request::ClosestPrecedingNode(id))?.into_node()?)),
+        }
+    }
+
+    pub(super) fn notify(&self, node: &Peer<I, V, M>) -> Result<()> {
+        match self {
+            Peer::Local(n) => n.clone().notify(node.clone()),
+            Peer::Remote(r) => r.call(Request::Notify(node.info()?))?.into_ok(),
+        }
+    }
+
+    pub(super) fn update_finger_table(&self, node: &Peer<I, V, M>, index: u32) -> Result<bool> {
+        match self {
+            Peer::Local(n) => Ok(n.update_finger_table(node, index)),
+            Peer::Remote(r) => r
+                .call(Request::UpdateFingerTable {
+                    node: node.info()?,
+                    index,
+                })?
+                .into_bool(),
+        }
+    }
+
+    pub(super) fn update_finger_table_leave(
+        &self,
+        node: &Peer<I, V, M>,
+        index: u32,
+        leave_id: I,
+    ) -> Result<bool> {
+        match self {
+            Peer::Local(n) => Ok(n.update_finger_table_leave(node, index, leave_id)),
+            Peer::Remote(r) => r
+                .call(Request::UpdateFingerTableLeave {
+                    node: node.info()?,
+                    index,
+                    leave_id,
+                })?
+                .into_bool(),
+        }
+    }
+
+    pub(super) fn get_keys(&self, start: I, end: I) -> Result<Vec<(Key<I>, V)>> {
+        match self {
+            Peer::Local(n) => Ok(n.inner().local_keys.range(start, end)),
+            Peer::Remote(r) => r.call(Request::GetKeys { start, end })?.into_entries(),
+        }
+    }
+
+    pub(super) fn transfer_keys(&self, start: I, end: I) -> Result<Vec<(Key<I>, V)>> {
+        match self {
+            Peer::Local(n) => {
+                let moved = n.inner().local_keys.drain_range(start, end)?;
+                Metrics::count(&n.counters().keys_migrated_out, moved.len());
+                Ok(moved)
+            }
+            Peer::Remote(r) => r.call(Request::TransferKeys { start, end })?.into_entries(),
+        }
+    }
+
+    pub(super) fn get(&self, key: &Key<I>) -> Result<Option<V>> {
+        match self {
+            Peer::Local(n) => Ok(n.get_entry(key)),
+            Peer::Remote(r) => r.call(Request::Get(key.clone()))?.into_value(),
+        }
+    }
+
+    pub(super) fn put(&self, key: Key<I>, value: V) -> Result<()> {
+        match self {
+            Peer::Local(n) => n.inner().local_keys.put(key, value),
+            Peer::Remote(r) => r.call(Request::Put(key, value))?.into_ok(),
+        }
+    }
+
+    /// Deletes a key the peer owns, returning whether it had the key.
+    pub(super) fn delete(&self, key: &Key<I>) -> Result<bool> {
+        match self {
+            Peer::Local(n) => Ok(n.inner().local_keys.delete(key)?.is_some()),
+            Peer::Remote(r) => r.call(Request::Delete(key.clone()))?.into_bool(),
+        }
+    }
+
+    pub(super) fn put_replica(&self, key: Key<I>, value: V) -> Result<()> {
+        match self {
+            Peer::Local(n) => n.inner().replicas.put(key, value),
+            Peer::Remote(r) => r.call(Request::PutReplica(key, value))?.into_ok(),
+        }
+    }
+
+    pub(super) fn delete_replica(&self, key: &Key<I>) -> Result<()> {
+        match self {
+            Peer::Local(n) => n.inner().replicas.delete(key).map(drop),
+            Peer::Remote(r) => r.call(Request::DeleteReplica(key.clone()))?.into_ok(),
+        }
+    }
+}
+
+impl<I: Identifier, V: Clone + Send + 'static, const M: u32> fmt::Display for Peer<I, V, M> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.id())
+    }
+}
+
+impl<I: Identifier, V: Clone + Send + 'static, const M: u32> Node<I, V, M> {
+    /// How remote nodes can reach this node.
+    pub fn info(&self) -> Result<NodeInfo<I>> {
+        match &self.inner().address {
+            Some(address) => Ok(NodeInfo {
+                id: self.id(),
+                address: address.clone(),
+            }),
+            None => Err(Error::NoAddress {
+                node: self.id().to_string(),
+            }),
+        }
+    }
+
+    /// Turns a node named in a request into a peer of this node.
+    fn peer(&self, info: NodeInfo<I>) -> Result<Peer<I, V, M>> {
+        let node_inner = self.inner();
+        if node_inner.address.as_ref() == Some(&info.address) {
+            return Ok(self.as_peer());
+        }
+        let transport = node_inner
+            .transport
+            .clone()
+            .ok_or_else(|| Error::NoAddress {
+                node: self.id().to_string(),
+            })?;
+        let origin = node_inner
+            .address
+            .clone()
+            .map(|address| (address, Arc::downgrade(&self.node_inner)));
+        Ok(Peer::Remote(RemoteNode {
+            origin,
+            metrics: Some(node_inner.metrics.clone()),
+            ..RemoteNode::new(info, transport)
+        }))
+    }
+
+    fn maybe_peer(&self, info: Option<NodeInfo<I>>) -> Result<Option<Peer<I, V, M>>> {
+        info.map(|info| self.peer(info)).transpose()
+    }
+
+    /// Checks a finger index named in a request, which the finger table
+    /// would otherwise panic on if it is outside `1..=M`.
+    fn finger_index(index: u32) -> Result<u32> {
+        if (1..=M).contains(&index) {
+            Ok(index)
+        } else {
+            Err(Error::Protocol(format!(
+                "finger index {} outside 1..={}",
+                index, M
+            )))
+        }
+    }
+}
+
+impl<I: Identifier, V: Clone + Send + fmt::Debug + 'static, const M: u32> Node<I, V, M> {
+    /// Answers a request sent by a remote peer or client.
+    pub fn handle(&mut self, request: Request<I, V>) -> Response<I, V> {
+        Metrics::count(&self.counters().rpcs_received, 1);
+        self.try_handle(request).unwrap_or_else(Response::Error)
+    }
+
+    fn try_handle(&mut self, request: Request<I, V>) -> Result<Response<I, V>> {
+        let response = match request {
+            Request::Ping => Response::Bool(self.is_alive()),
+            Request::Info => Response::Node(self.info()?),
+            Request::FindSuccessor(id) => Response::Node(self.find_successor(id)?.info()?),
+            Request::GetSuccessor => Response::Node(self.successor()?.info()?),
+            Request::GetSuccessorList => Response::Nodes(
+                self.as_peer()
+                    .successor_list()?
+                    .iter()
+                    .map(|s| s.info())
+                    .collect::<Result<_>>()?,
+            ),
+            Request::GetPredecessor => {
+                Response::MaybeNode(self.predecessor().map(|p| p.info()).transpose()?)
+            }
+            Request::SetPredecessor(info) => {
+                let predecessor = self.maybe_peer(info)?;
+                self.as_peer().set_predecessor(predecessor.as_ref())?;
+                Response::Ok
+            }
+            Request::SetSuccessor(info) => {
+                let successor = self.peer(info)?;
+                self.as_peer().set_successor(&successor)?;
+                Response::Ok
+            }
+            Request::ClosestPrecedingNode(id) => {
+                Response::Node(self.closest_preceding_node(id)?.info()?)
+            }
+            Request::Route { id, path } => {
+                let (node, path) = self.route(id, path)?;
+                Response::Route(node.info()?, path)
+            }
+            Request::Notify(info) => {
+                let node = self.peer(info)?;
+                self.notify(node)?;
+                Response::Ok
+            }
+            Request::UpdateFingerTable { node, index } => {
+                let index = Self::finger_index(index)?;
+                let node = self.peer(node)?;
+                Response::Bool(self.update_finger_table(&node, index))
+            }
+            Request::UpdateFingerTableLeave {
+                node,
+                index,
+                leave_id,
+            } => {
+                let index = Self::finger_index(index)?;
+                let node = self.peer(node)?;
+                Response::Bool(self.update_finger_table_leave(&node, index, leave_id))
+            }
+            Request::GetKeys { start, end } => {
+                Response::Entries(self.as_peer().get_keys(start, end)?)
+            }
+            Request::TransferKeys { start, end } => {
+                Response::Entries(self.as_peer().transfer_keys(start, end)?)
+            }
+            Request::Get(key) => Response::from_value(self.get_entry(&key)),
+            Request::Put(key, value) => {
+                self.as_peer().put(key, value)?;
+                Response::Ok
+            }
+            Request::Delete(key) => Response::Bool(self.as_peer().delete(&key)?),
+            Request::PutReplica(key, value) => {
+                self.as_peer().put_replica(key, value)?;
+                Response::Ok
+            }
+            Request::DeleteReplica(key) => {
+                self.as_peer().delete_replica(&key)?;
+                Response::Ok
+            }
+            Request::Join(info) => {
+                let node = self.maybe_peer(info)?;
+                self.join_peer(node)?;
+                Response::Ok
+            }
+            Request::JoinStabilized(info) => {
+                let node = self.maybe_peer(info)?;
+                self.join_stabilized_peer(node)?;
+                Response::Ok
+            }
+            Request::Leave => {
+                self.leave()?;
+                Response::Ok
+            }
+            Request::Tick => {
+                self.tick()?;
+                Response::Ok
+            }
+            Request::Find(key) => Response::from_value(self.lookup_entry(&key)?.value),
+            Request::Insert(key, value) => {
+                self.insert_entry(key, value)?;
+                Response::Ok
+            }
+            Request::Remove(key) => {
+                self.remove_entry(&key)?;
+                Response::Ok
+            }
+            Request::Keys => Response::Entries(self.keys()),
+        };
+        Ok(response)
+    }
+}
@@ -0,0 +1,152 @@
+//! Replication of every key onto the `replication_factor - 1` nodes that
+//! follow its owner, so keys survive abrupt failures.
+//!
+//! Replicas live apart from a node's own keys so that ownership-based
+//! migration never mixes the two. Periodic maintenance promotes the replicas
+//! a node inherits when its predecessor fails, drops those it no longer
+//! holds and re-replicates each node's keys onto its current successors.
+//! Until then, lookups whose owner does not answer read the replicas.
+
+use super::{Node, Peer};
+use crate::error::{Error, Result};
+use crate::hash::Key;
+use crate::id::Identifier;
+use crate::storage::Storage;
+
+impl<I: Identifier, V: Clone + Send + 'static, const M: u32> Node<I, V, M> {
+    /// The live successors of `owner` holding replicas of its keys.
+    fn replica_holders(&self, owner: &Peer<I, V, M>) -> Result<Vec<Peer<I, V, M>>> {
+        let copies = self.inner().config.replication_factor.saturating_sub(1);
+        let mut holders: Vec<Peer<I, V, M>> = Vec::new();
+        if copies == 0 {
+            return Ok(holders);
+        }
+        for s in owner.successor_list()? {
+            if holders.len() == copies {
+                break;
+            }
+            if s.is_alive() && s.id() != owner.id() && !holders.iter().any(|h| h.id() == s.id()) {
+                holders.push(s);
+            }
+        }
+        Ok(holders)
+    }
+
+    pub(super) fn put_replicas(
+        &self,
+        owner: &Peer<I, V, M>,
+        key: &Key<I>,
+        value: &V,
+    ) -> Result<()> {
+        for holder in self.replica_holders(owner)? {
+            holder.put_replica(key.clone(), value.clone())?;
+        }
+        Ok(())
+    }
+
+    pub(super) fn delete_replicas(&self, owner: &Peer<I, V, M>, key: &Key<I>) -> Result<()> {
+        for holder in self.replica_holders(owner)? {
+            holder.delete_replica(key)?;
+        }
+        Ok(())
+    }
+
+    /// Looks `key` up on the replica holders of `owner`, which failed with
+    /// `error`, returning the first holder that answers along with its
+    /// value, or `error` if none does.
+    pub(super) fn get_replica(
+        &self,
+        key: &Key<I>,
+        owner: &Peer<I, V, M>,
+        error: Error,
+    ) -> Result<(Peer<I, V, M>, Option<V>)> {
+        let copies = self.inner().config.replication_factor.saturating_sub(1);
+        if copies == 0 {
+            return Err(error);
+        }
+        // the holders follow the owner, which its predecessor still lists
+        let holders = self
+            .find_predecessor(key.id)?
+            .successor_list()?
+            .into_iter()
+            .filter(|s| s.id() != owner.id())
+            .take(copies);
+        for holder in holders {
+            if let Ok(value) = holder.get(key) {
+                return Ok((holder, value));
+            }
+        }
+        Err(error)
+    }
+
+    /// Takes ownership of replicas in `(predecessor, self]`, which this node
+    /// inherits when its predecessor fails.
+    pub(super) fn promote_replicas(&mut self) -> Result<()> {
+        let self_id = self.id();
+        let predecessor_id = match self.predecessor().filter(|p| p.is_alive()) {
+            Some(p) if p.id() != self_id => p.id(),
+            // without a predecessor the owned range is unknown
+            _ => return Ok(()),
+        };
+        let promoted = self.inner().replicas.drain_range(predecessor_id, self_id)?;
+        let mut node_inner = self.inner();
+        for (k, v) in promoted {
+            if node_inner.local_keys.get(&k).is_none() {
+                node_inner.local_keys.put(k, v)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Drops the replicas whose owner no longer counts this node among its
+    /// replica holders, as happens when a node joins in between.
+    ///
+    /// Removing a key only reaches the current holders, so a copy kept here
+    /// could later be promoted over the removal, or over a newer value.
+    pub(super) fn drop_stale_replicas(&mut self) -> Result<()> {
+        let self_id = self.id();
+        let replicas = self.inner().replicas.range(self_id, self_id);
+        // keys in [start, owner] share the owner found for start
+        let mut owned: Option<(I, I, bool)> = None;
+        for (k, _) in replicas {
+            let keep = match owned {
+                Some((start, owner, keep))
+                    if k.id == owner
+                        || (start != owner && self.e_is_between_ring(k.id, start, owner)) =>
+                {
+                    keep
+                }
+                _ => {
+                    let owner = self.find_successor(k.id)?;
+                    // replicas this node now owns are promoted instead
+                    let keep = owner.id() == self_id
+                        || self
+                            .replica_holders(&owner)?
+                            .iter()
+                            .any(|h| h.id() == self_id);
+                    owned = Some((k.id, owner.id(), keep));
+                    keep
+                }
+            };
+            if !keep {
+                self.inner().replicas.delete(&k)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Copies every key this node owns onto its current replica holders.
+    pub(super) fn replicate_keys(&mut self) -> Result<()> {
+        let holders = self.replica_holders(&self.as_peer())?;
+        if holders.is_empty() {
+            return Ok(());
+        }
+        let keys = self.keys();
+        for holder in holders {
+            for (k, v) in keys.iter() {
+                holder.put_replica(k.clone(), v.clone())?;
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,162 @@
+//! Stabilization-based maintenance from section IV of the Chord paper.
+//!
+//! Instead of rewiring every affected finger table when a node joins, a
+//! joining node only learns its successor. Each node then periodically runs
+//! [`Node::tick`], which verifies its successor, notifies it, refreshes one
+//! finger, drops a failed predecessor, hands misplaced keys back towards
+//! their owner and repairs replicas. Concurrent joins and failures converge to a correct ring
+//! after enough ticks.
+
+use super::{Node, Peer};
+use crate::error::Result;
+use crate::event::Event;
+use crate::id::Identifier;
+use crate::metrics::Metrics;
+use std::sync::Arc;
+
+impl<I: Identifier, V: Clone + Send + 'static, const M: u32> Node<I, V, M> {
+    /// Joins the ring known to `node` by only looking up this node's
+    /// successor; the rest of the state is filled in by later ticks.
+    pub fn join_stabilized(&mut self, node: Option<Node<I, V, M>>) -> Result<()> {
+        self.join_stabilized_peer(node.map(Peer::Local))
+    }
+
+    pub(super) fn join_stabilized_peer(&mut self, node: Option<Peer<I, V, M>>) -> Result<()> {
+        let state = Arc::clone(&self.node_inner);
+        let _membership = state.lock_membership();
+        Self::check_id(self.id())?;
+        self.reset()?;
+        let successor = match node {
+            Some(n) => {
+                self.claim_id(&n)?;
+                n.find_successor(self.id())?
+            }
+            // first node to join the chord
+            None => self.as_peer(),
+        };
+        self.inner().alive = true;
+        for i in 1..=M {
+            self.set_finger(i, successor.clone());
+        }
+        self.update_successor_list()?;
+        self.emit(Event::NodeJoined { node: self.id() });
+        Ok(())
+    }
+
+    /// Runs one round of periodic maintenance.
+    ///
+    /// Every step runs even if an earlier one fails, and the first error is
+    /// returned. Errors are expected while the ring repairs itself, as a step
+    /// may reach a node that just failed or whose state has not settled yet;
+    /// later rounds retry, so callers ticking on a schedule can ignore them.
+    pub fn tick(&mut self) -> Result<()> {
+        if !self.is_alive() {
+            return Ok(());
+        }
+        let maintained = self.maintain();
+        let fixed = self.fix_fingers();
+        maintained.and(fixed)
+    }
+
+    /// Runs every step of [`Node::tick`] but [`Node::fix_fingers`], for
+    /// callers refreshing fingers on a schedule of their own.
+    pub fn maintain(&mut self) -> Result<()> {
+        let state = Arc::clone(&self.node_inner);
+        let _membership = state.lock_membership();
+        if !self.is_alive() {
+            return Ok(());
+        }
+        self.check_predecessor();
+        let stabilized = self.stabilize();
+        let promoted = self.promote_replicas();
+        let dropped = self.drop_stale_replicas();
+        let transferred = self.transfer_keys_to_predecessor();
+        let replicated = self.replicate_keys();
+        stabilized
+            .and(promoted)
+            .and(dropped)
+            .and(transferred)
+            .and(replicated)
+    }
+
+    /// Verifies this node's immediate successor, tells the successor about it
+    /// and refreshes the successor list from the successor's list.
+    pub fn stabilize(&mut self) -> Result<()> {
+        let self_id = self.id();
+        let successor = self.successor()?;
+        if let Some(x) = successor.predecessor()?.filter(|x| x.is_alive()) {
+            if x.id() != successor.id() && self.between(x.id(), self_id, successor.id()) {
+                self.set_finger(1, x);
+            }
+        }
+        self.successor()?.notify(&self.as_peer())?;
+        self.update_successor_list()
+    }
+
+    /// Called by `node`, which thinks it might be our predecessor.
+    ///
+    /// Keys the new predecessor owns move to it on the next tick rather than
+    /// here, since a remote `node` is still waiting for this call to return.
+    pub(super) fn notify(&mut self, node: Peer<I, V, M>) -> Result<()> {
+        let self_id = self.id();
+        let node_id = node.id();
+        let accept = match self.predecessor().filter(|p| p.is_alive()) {
+            None => true,
+            Some(p) => p.id() != node_id && self.between(node_id, p.id(), self_id),
+        };
+        if accept && node_id != self_id {
+            self.set_predecessor(Some(node));
+        }
+        Ok(())
+    }
+
+    /// Hands every key outside `(predecessor, self]` to the predecessor.
+    ///
+    /// Keys that arrive after a closer predecessor was accepted thereby keep
+    /// moving back along the ring until they reach their owner.
+    fn transfer_keys_to_predecessor(&mut self) -> Result<()> {
+        let self_id = self.id();
+        let predecessor = match self.predecessor().filter(|p| p.is_alive()) {
+            Some(p) if p.id() != self_id => p,
+            _ => return Ok(()),
+        };
+        let predecessor_id = predecessor.id();
+        let moved = self.inner().local_keys.range(self_id, predecessor_id);
+        if moved.is_empty() {
+            return Ok(());
+        }
+        for (k, _) in moved {
+            if self.move_key(&k, |k, v| predecessor.put(k, v))? {
+                Metrics::count(&self.counters().keys_migrated_out, 1);
+                self.emit(Event::KeyMigrated {
+                    key: k,
+                    from: self_id,
+                    to: predecessor_id,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// Refreshes the next finger table entry, cycling through all of them.
+    pub fn fix_fingers(&mut self) -> Result<()> {
+        let state = Arc::clone(&self.node_inner);
+        let _membership = state.lock_membership();
+        let next = {
+            let mut node_inner = self.inner();
+            node_inner.next_finger = node_inner.next_finger % M + 1;
+            node_inner.next_finger
+        };
+        let start = self.inner().finger_table.get_start(next);
+        let node = self.find_successor(start)?;
+        self.set_finger(next, node);
+        Ok(())
+    }
+
+    /// Clears the predecessor pointer if the predecessor has failed.
+    pub fn check_predecessor(&mut self) {
+        if self.predecessor().is_some_and(|p| !p.ping()) {
+            self.set_predecessor(None);
+        }
+    }
+}
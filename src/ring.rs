@@ -0,0 +1,241 @@
+//! A ring of nodes living in one process.
+
+use crate::audit::{self, AuditReport};
+use crate::config::Config;
+use crate::error::{Error, Result};
+use crate::event::Observer;
+use crate::export::{self, RingSnapshot};
+use crate::id::Identifier;
+use crate::metrics;
+use crate::node::{Lookup, Node};
+use crate::storage::MemoryStorage;
+use core::fmt;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+/// The nodes of a ring sharing this process, by identifier.
+///
+/// Nodes call each other directly and keep their keys in memory. Key
+/// operations on the ring are carried out by its first live node; any node
+/// can also be driven on its own through the handle from [`Ring::node`].
+/// Nodes that left or crashed stay listed until another node takes their
+/// identifier.
+pub struct Ring<I, V, const M: u32> {
+    config: Config,
+    nodes: BTreeMap<I, Node<I, V, M>>,
+    // subscribed to every node, including those created later
+    observers: Vec<Arc<dyn Observer<I>>>,
+}
+
+impl<I, V, const M: u32> Ring<I, V, M>
+where
+    I: Identifier,
+    V: Clone + Send + fmt::Debug + 'static,
+{
+    pub fn new() -> Self {
+        Self::with_config(Config::default())
+    }
+
+    /// Creates an empty ring whose nodes will all use `config`.
+    pub fn with_config(config: Config) -> Self {
+        Self {
+            config,
+            nodes: BTreeMap::new(),
+            observers: Vec::new(),
+        }
+    }
+
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    /// Creates node `id`, which stays outside the ring until it joins, in
+    /// place of a node `id` that left or crashed.
+    pub fn create(&mut self, id: I) -> Result<Node<I, V, M>> {
+        if self.nodes.get(&id).is_some_and(|n| n.is_alive()) {
+            return Err(Error::DuplicateId { id: id.to_string() });
+        }
+        let node = self.new_node(id);
+        self.nodes.insert(id, node.clone());
+        Ok(node)
+    }
+
+    /// Joins node `id` to the ring through node `bootstrap`, or makes it
+    /// start the ring if `None`.
+    ///
+    /// Returns the node's identifier, which differs from `id` if it had to
+    /// take a new one; see [`Config::id_collision`].
+    pub fn join(&mut self, id: I, bootstrap: Option<I>) -> Result<I> {
+        let bootstrap = bootstrap.map(|b| self.get(b)).transpose()?;
+        let mut node = self.get(id)?;
+        node.join(bootstrap)?;
+        if node.id() != id {
+            self.nodes.remove(&id);
+            self.nodes.insert(node.id(), node.clone());
+        }
+        Ok(node.id())
+    }
+
+    /// Creates node `id` and joins it through the first live node, or makes
+    /// it start the ring if there is none.
+    ///
+    /// If `id` is taken, the join fails or the node takes a new identifier,
+    /// as set by [`Config::id_collision`]. The node replaces any node listed
+    /// under its final identifier that is not part of the ring.
+    pub fn add(&mut self, id: I) -> Result<Node<I, V, M>> {
+        let bootstrap = self.live_nodes().next().cloned();
+        let mut node = self.new_node(id);
+        node.join(bootstrap)?;
+        self.nodes.insert(node.id(), node.clone());
+        Ok(node)
+    }
+
+    /// Makes node `id` leave the ring, handing its keys to its successor.
+    pub fn leave(&mut self, id: I) -> Result<()> {
+        self.get(id)?.leave()
+    }
+
+    /// Stops node `id` without telling the other nodes, as a failure would.
+    pub fn crash(&mut self, id: I) -> Result<()> {
+        self.get(id)?.crash();
+        Ok(())
+    }
+
+    /// Reports the events of every node, present and future, to `observer`.
+    pub fn subscribe(&mut self, observer: Arc<dyn Observer<I>>) {
+        for node in self.nodes.values() {
+            node.subscribe(observer.clone());
+        }
+        self.observers.push(observer);
+    }
+
+    /// A handle to node `id`.
+    pub fn node(&self, id: I) -> Option<Node<I, V, M>> {
+        self.nodes.get(&id).cloned()
+    }
+
+    /// Every node, whether part of the ring or not, by identifier.
+    pub fn nodes(&self) -> impl Iterator<Item = &Node<I, V, M>> {
+        self.nodes.values()
+    }
+
+    /// The nodes that are part of the ring, by identifier.
+    pub fn live_nodes(&self) -> impl Iterator<Item = &Node<I, V, M>> {
+        self.nodes.values().filter(|n| n.is_alive())
+    }
+
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    /// Runs `rounds` rounds of maintenance on every live node and returns
+    /// how many of them failed; see [`Node::tick`].
+    pub fn tick(&mut self, rounds: usize) -> usize {
+        let mut failures = 0;
+        for _ in 0..rounds {
+            for node in self.nodes.values_mut().filter(|n| n.is_alive()) {
+                if node.tick().is_err() {
+                    failures += 1;
+                }
+            }
+        }
+        failures
+    }
+
+    /// Checks the live nodes for inconsistencies; see [`audit`].
+    pub fn audit(&self) -> AuditReport<I> {
+        audit::audit(self.nodes())
+    }
+
+    /// Records the state of the live nodes; see [`export`].
+    pub fn snapshot(&self) -> RingSnapshot<I> {
+        export::snapshot(self.nodes())
+    }
+
+    /// Draws the live nodes as a Graphviz graph; see [`export::to_dot`].
+    pub fn to_dot(&self) -> String {
+        export::to_dot(self.nodes())
+    }
+
+    /// Writes the state of the live nodes as JSON; see [`export::to_json`].
+    pub fn to_json(&self) -> String
+    where
+        I: Serialize,
+    {
+        export::to_json(self.nodes())
+    }
+
+    /// Renders the counters of the live nodes in the Prometheus text format;
+    /// see [`metrics::to_prometheus`].
+    pub fn to_prometheus(&self) -> String {
+        metrics::to_prometheus(self.nodes())
+    }
+
+    pub fn insert(&mut self, key: I, value: V) -> Result<()> {
+        self.entry()?.insert(key, value)
+    }
+
+    /// Stores `value` under the identifier `key` hashes to.
+    pub fn insert_key(&mut self, key: impl AsRef<[u8]>, value: V) -> Result<()> {
+        self.entry()?.insert_key(key, value)
+    }
+
+    pub fn find(&self, key: I) -> Result<Option<V>> {
+        self.entry()?.find(key)
+    }
+
+    pub fn find_key(&self, key: impl AsRef<[u8]>) -> Result<Option<V>> {
+        self.entry()?.find_key(key)
+    }
+
+    /// Looks up a key, reporting the path the lookup took.
+    pub fn lookup(&self, key: I) -> Result<Lookup<I, V>> {
+        self.entry()?.lookup(key)
+    }
+
+    pub fn lookup_key(&self, key: impl AsRef<[u8]>) -> Result<Lookup<I, V>> {
+        self.entry()?.lookup_key(key)
+    }
+
+    pub fn remove(&mut self, key: I) -> Result<()> {
+        self.entry()?.remove(key)
+    }
+
+    pub fn remove_key(&mut self, key: impl AsRef<[u8]>) -> Result<()> {
+        self.entry()?.remove_key(key)
+    }
+
+    fn new_node(&self, id: I) -> Node<I, V, M> {
+        let node = Node::with_config(id, self.config.clone(), Box::new(MemoryStorage::new()));
+        for observer in self.observers.iter() {
+            node.subscribe(observer.clone());
+        }
+        node
+    }
+
+    fn get(&self, id: I) -> Result<Node<I, V, M>> {
+        self.node(id).ok_or_else(|| Error::UnknownNode {
+            node: id.to_string(),
+        })
+    }
+
+    /// The node key operations start from.
+    fn entry(&self) -> Result<Node<I, V, M>> {
+        self.live_nodes().next().cloned().ok_or(Error::EmptyRing)
+    }
+}
+
+impl<I, V, const M: u32> Default for Ring<I, V, M>
+where
+    I: Identifier,
+    V: Clone + Send + fmt::Debug + 'static,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
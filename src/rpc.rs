@@ -0,0 +1,204 @@
+//! Messages exchanged between nodes that do not share a process.
+//!
+//! Every Chord operation a node performs on another node has a [`Request`]
+//! variant, answered by the receiving node through [`Node::handle`]. A few
+//! more requests let a client drive a node from outside, such as asking it
+//! to join a ring or to look up a key on its behalf.
+//!
+//! [`Node::handle`]: crate::node::Node::handle
+
+use crate::error::{Error, Result};
+use crate::hash::Key;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+mod memory;
+
+pub use memory::MemoryTransport;
+
+/// How to reach a node: its identifier and the address it listens on.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NodeInfo<I> {
+    pub id: I,
+    pub address: String,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum Request<I, V> {
+    Ping,
+    Info,
+    FindSuccessor(I),
+    GetSuccessor,
+    GetSuccessorList,
+    GetPredecessor,
+    SetPredecessor(Option<NodeInfo<I>>),
+    SetSuccessor(NodeInfo<I>),
+    ClosestPrecedingNode(I),
+    /// Resolves the successor of `id` recursively; `path` holds the nodes the
+    /// query has already passed through.
+    Route {
+        id: I,
+        path: Vec<I>,
+    },
+    Notify(NodeInfo<I>),
+    UpdateFingerTable {
+        node: NodeInfo<I>,
+        index: u32,
+    },
+    UpdateFingerTableLeave {
+        node: NodeInfo<I>,
+        index: u32,
+        leave_id: I,
+    },
+    /// Copies the keys in `(start, end]`.
+    GetKeys {
+        start: I,
+        end: I,
+    },
+    /// Removes and returns the keys in `(start, end]`.
+    TransferKeys {
+        start: I,
+        end: I,
+    },
+    Get(Key<I>),
+    Put(Key<I>, V),
+    /// Answered with whether the node had the key.
+    Delete(Key<I>),
+    PutReplica(Key<I>, V),
+    DeleteReplica(Key<I>),
+    // client requests, carried out by the receiving node
+    Join(Option<NodeInfo<I>>),
+    JoinStabilized(Option<NodeInfo<I>>),
+    Leave,
+    Tick,
+    Find(Key<I>),
+    Insert(Key<I>, V),
+    Remove(Key<I>),
+    Keys,
+}
+
+impl<I, V> Request<I, V> {
+    /// Whether a node that is not part of a ring, because it has not joined
+    /// yet or has left or crashed, answers the request: only those that
+    /// tell about it or make it join do.
+    pub fn is_answered_outside_ring(&self) -> bool {
+        matches!(
+            self,
+            Request::Ping | Request::Info | Request::Join(_) | Request::JoinStabilized(_)
+        )
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum Response<I, V> {
+    Ok,
+    Bool(bool),
+    Node(NodeInfo<I>),
+    MaybeNode(Option<NodeInfo<I>>),
+    Nodes(Vec<NodeInfo<I>>),
+    /// A node found by a lookup, with the path the lookup took.
+    Route(NodeInfo<I>, Vec<I>),
+    // a missing value is its own variant, since JSON cannot tell `None`
+    // from `Some(None)` when `V` is itself an `Option`
+    Found(V),
+    NotFound,
+    Entries(Vec<(Key<I>, V)>),
+    Error(Error),
+}
+
+impl<I, V> Response<I, V> {
+    pub fn from_value(value: Option<V>) -> Self {
+        match value {
+            Some(value) => Response::Found(value),
+            None => Response::NotFound,
+        }
+    }
+
+    pub fn into_ok(self) -> Result<()> {
+        match self {
+            Response::Ok => Ok(()),
+            other => Err(other.unexpected()),
+        }
+    }
+
+    pub fn into_bool(self) -> Result<bool> {
+        match self {
+            Response::Bool(b) => Ok(b),
+            other => Err(other.unexpected()),
+        }
+    }
+
+    pub fn into_node(self) -> Result<NodeInfo<I>> {
+        match self {
+            Response::Node(info) => Ok(info),
+            other => Err(other.unexpected()),
+        }
+    }
+
+    pub fn into_maybe_node(self) -> Result<Option<NodeInfo<I>>> {
+        match self {
+            Response::MaybeNode(info) => Ok(info),
+            other => Err(other.unexpected()),
+        }
+    }
+
+    pub fn into_nodes(self) -> Result<Vec<NodeInfo<I>>> {
+        match self {
+            Response::Nodes(infos) => Ok(infos),
+            other => Err(other.unexpected()),
+        }
+    }
+
+    pub fn into_route(self) -> Result<(NodeInfo<I>, Vec<I>)> {
+        match self {
+            Response::Route(info, path) => Ok((info, path)),
+            other => Err(other.unexpected()),
+        }
+    }
+
+    pub fn into_value(self) -> Result<Option<V>> {
+        match self {
+            Response::Found(value) => Ok(Some(value)),
+            Response::NotFound => Ok(None),
+            other => Err(other.unexpected()),
+        }
+    }
+
+    pub fn into_entries(self) -> Result<Vec<(Key<I>, V)>> {
+        match self {
+            Response::Entries(entries) => Ok(entries),
+            other => Err(other.unexpected()),
+        }
+    }
+
+    fn unexpected(self) -> Error {
+        match self {
+            Response::Error(e) => e,
+            _ => Error::Protocol("unexpected response".to_string()),
+        }
+    }
+}
+
+/// Carries requests between nodes.
+///
+/// [`crate::net::TcpTransport`] reaches nodes in other processes, while
+/// [`MemoryTransport`] delivers requests within one process so the protocol
+/// can be tested deterministically without sockets.
+pub trait Transport<I, V>: Send + Sync {
+    /// Sends `request` to the node listening on `address` and waits for its response.
+    fn call(&self, address: &str, request: Request<I, V>) -> Result<Response<I, V>>;
+
+    /// Like [`Transport::call`], but giving up after `timeout`.
+    ///
+    /// Transports that answer without waiting, such as [`MemoryTransport`],
+    /// need not bound the call.
+    fn call_with_timeout(
+        &self,
+        address: &str,
+        request: Request<I, V>,
+        timeout: Duration,
+    ) -> Result<Response<I, V>> {
+        let _ = timeout;
+        self.call(address, request)
+    }
+}
@@ -0,0 +1,78 @@
+use super::{Request, Response, Transport};
+use crate::error::{Error, Result};
+use crate::id::Identifier;
+use crate::node::{Node, WeakNodeRef};
+use core::fmt;
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex, PoisonError},
+};
+
+/// Delivers requests to nodes in the same process by calling their
+/// [`Node::handle`] directly.
+///
+/// A node that has crashed or left does not answer, as it would not over a
+/// network, except for the requests that let it join again; see
+/// [`Request::is_answered_outside_ring`].
+pub struct MemoryTransport<I, V, const M: u32> {
+    nodes: Mutex<HashMap<String, WeakNodeRef<I, V, M>>>,
+}
+
+impl<I: Identifier, V: Clone + Send + 'static, const M: u32> MemoryTransport<I, V, M> {
+    pub fn new() -> Self {
+        Self {
+            nodes: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Makes `node` reachable at its address.
+    pub fn register(&self, node: &Node<I, V, M>) -> Result<()> {
+        let address = node.info()?.address;
+        self.nodes
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .insert(address, Arc::downgrade(&node.node_inner));
+        Ok(())
+    }
+
+    /// Disconnects the node at `address`.
+    pub fn unregister(&self, address: &str) {
+        self.nodes
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .remove(address);
+    }
+}
+
+impl<I: Identifier, V: Clone + Send + 'static, const M: u32> Default for MemoryTransport<I, V, M> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<I, V, const M: u32> Transport<I, V> for MemoryTransport<I, V, M>
+where
+    I: Identifier,
+    V: Clone + Send + fmt::Debug + 'static,
+{
+    fn call(&self, address: &str, request: Request<I, V>) -> Result<Response<I, V>> {
+        let node_inner = self
+            .nodes
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get(address)
+            .and_then(|n| n.upgrade())
+            .ok_or_else(|| Error::NodeUnreachable {
+                address: address.to_string(),
+                reason: "no such node".to_string(),
+            })?;
+        let mut node = Node::new_inner(node_inner);
+        if !node.is_alive() && !request.is_answered_outside_ring() {
+            return Err(Error::NodeUnreachable {
+                address: address.to_string(),
+                reason: "no response".to_string(),
+            });
+        }
+        Ok(node.handle(request))
+    }
+}
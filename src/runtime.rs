@@ -0,0 +1,169 @@
+//! Running nodes on the tokio runtime.
+//!
+//! [`AsyncNode`] exposes the node API as async functions. Node operations
+//! take locks and, over a network transport, wait on peers, so each one runs
+//! on tokio's blocking thread pool instead of stalling the async workers.
+//! Once joined, a node stabilizes and fixes its fingers from background tasks
+//! at the intervals set in its [`Config`](crate::config::Config).
+
+use crate::error::{Error, Result};
+use crate::id::Identifier;
+use crate::node::Node;
+use core::fmt;
+use std::{
+    mem, panic,
+    sync::{Mutex, PoisonError},
+    time::Duration,
+};
+use tokio::{
+    sync::watch,
+    task::{self, JoinHandle},
+    time::{self, MissedTickBehavior},
+};
+
+/// A node driven from async code, with its periodic maintenance running in
+/// the background.
+///
+/// Dropping an `AsyncNode` stops its maintenance tasks without leaving the
+/// ring, as a crash would; [`AsyncNode::shutdown`] hands its keys over first.
+pub struct AsyncNode<I, V, const M: u32> {
+    node: Node<I, V, M>,
+    stop: watch::Sender<bool>,
+    tasks: Mutex<Vec<JoinHandle<()>>>,
+}
+
+impl<I, V, const M: u32> AsyncNode<I, V, M>
+where
+    I: Identifier,
+    V: Clone + Send + fmt::Debug + 'static,
+{
+    pub fn new(node: Node<I, V, M>) -> Self {
+        Self {
+            node,
+            stop: watch::channel(false).0,
+            tasks: Mutex::new(Vec::new()),
+        }
+    }
+
+    pub fn id(&self) -> I {
+        self.node().id()
+    }
+
+    /// A handle to the underlying node, e.g. to pass as the bootstrap node of
+    /// another join.
+    pub fn node(&self) -> Node<I, V, M> {
+        self.node.clone()
+    }
+
+    /// Joins the ring through `node`, or starts a new ring if `None`, then
+    /// starts the background maintenance that integrates this node.
+    pub async fn join(&self, node: Option<Node<I, V, M>>) -> Result<()> {
+        let mut this = self.node();
+        blocking(move || this.join_stabilized(node)).await?;
+        self.start_maintenance();
+        Ok(())
+    }
+
+    /// Stops the background maintenance and leaves the ring, handing this
+    /// node's keys to its successor.
+    pub async fn leave(&self) -> Result<()> {
+        self.stop_maintenance().await;
+        let mut this = self.node();
+        blocking(move || this.leave()).await
+    }
+
+    /// Leaves the ring if this node is still part of it and releases the node.
+    pub async fn shutdown(self) -> Result<()> {
+        if self.node().is_alive() {
+            self.leave().await
+        } else {
+            self.stop_maintenance().await;
+            Ok(())
+        }
+    }
+
+    pub async fn find(&self, key: I) -> Result<Option<V>> {
+        let this = self.node();
+        blocking(move || this.lookup(key).map(|l| l.value)).await
+    }
+
+    pub async fn insert(&self, key: I, value: V) -> Result<()> {
+        let mut this = self.node();
+        blocking(move || this.insert(key, value)).await
+    }
+
+    pub async fn remove(&self, key: I) -> Result<()> {
+        let mut this = self.node();
+        blocking(move || this.remove(key)).await
+    }
+
+    fn start_maintenance(&self) {
+        let node = self.node();
+        let config = node.config();
+        let mut tasks = self.tasks.lock().unwrap_or_else(PoisonError::into_inner);
+        tasks.push(tokio::spawn(run_periodically(
+            node.clone(),
+            config.stabilize_interval,
+            self.stop.subscribe(),
+            Node::maintain,
+        )));
+        tasks.push(tokio::spawn(run_periodically(
+            node,
+            config.fix_fingers_interval,
+            self.stop.subscribe(),
+            Node::fix_fingers,
+        )));
+    }
+
+    /// Stops the background tasks and waits for a round in progress to end.
+    async fn stop_maintenance(&self) {
+        self.stop.send_replace(true);
+        let tasks = mem::take(&mut *self.tasks.lock().unwrap_or_else(PoisonError::into_inner));
+        for t in tasks {
+            let _ = t.await;
+        }
+    }
+}
+
+impl<I, V, const M: u32> Drop for AsyncNode<I, V, M> {
+    fn drop(&mut self) {
+        self.stop.send_replace(true);
+    }
+}
+
+/// Runs `f` on the blocking thread pool. A panic in `f` is passed on to the
+/// caller; a task cancelled because the runtime shuts down is an error.
+async fn blocking<T, F>(f: F) -> Result<T>
+where
+    T: Send + 'static,
+    F: FnOnce() -> Result<T> + Send + 'static,
+{
+    match task::spawn_blocking(f).await {
+        Ok(result) => result,
+        Err(e) if e.is_panic() => panic::resume_unwind(e.into_panic()),
+        Err(_) => Err(Error::Shutdown),
+    }
+}
+
+/// Runs `step` on `node` every `period` until `stop` changes.
+async fn run_periodically<I, V, const M: u32>(
+    node: Node<I, V, M>,
+    period: Duration,
+    mut stop: watch::Receiver<bool>,
+    step: fn(&mut Node<I, V, M>) -> Result<()>,
+) where
+    I: Identifier,
+    V: Clone + Send + 'static,
+{
+    let mut interval = time::interval(period);
+    interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    loop {
+        tokio::select! {
+            _ = interval.tick() => {}
+            _ = stop.changed() => return,
+        }
+        let mut node = node.clone();
+        // see `Node::tick` on why errors are ignored
+        let _ = task::spawn_blocking(move || step(&mut node)).await;
+    }
+}
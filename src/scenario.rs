@@ -0,0 +1,179 @@
+//! Scenario files: scripted ring experiments with their expected outcome.
+//!
+//! A scenario is a text file of [`Shell`] commands, one per line, mixed with
+//! checks of the state they leave behind:
+//!
+//! ```text
+//! # blank lines and lines starting with `#` are skipped
+//! create 0
+//! join 0
+//! insert 0 42 hello
+//! expect value 0 42 hello
+//! expect missing 0 7
+//! expect keys 0 42
+//! expect error delete 0 7
+//! ```
+//!
+//! `expect value <node> <key> <value>` and `expect missing <node> <key>` look
+//! the key up from the node, `expect keys <node> <key>...` lists every key the
+//! node owns, and `expect error <command>` runs a command that must fail.
+//! A scenario runs to the end; every command that fails and every check that
+//! does not hold is reported as a mismatch.
+
+use crate::error::{IoContext, Result};
+use crate::shell::{Shell, ShellNode};
+use core::fmt;
+use std::{fs, path::Path};
+
+/// A line of a scenario whose outcome differs from what it expects.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Mismatch {
+    /// Line number, starting at 1.
+    pub line: usize,
+    pub text: String,
+    pub problem: String,
+}
+
+/// The outcome of running a scenario.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ScenarioReport {
+    pub commands: usize,
+    pub checks: usize,
+    pub mismatches: Vec<Mismatch>,
+}
+
+impl ScenarioReport {
+    pub fn passed(&self) -> bool {
+        self.mismatches.is_empty()
+    }
+}
+
+impl fmt::Display for ScenarioReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} commands, {} checks, {} mismatches",
+            self.commands,
+            self.checks,
+            self.mismatches.len()
+        )?;
+        for m in self.mismatches.iter() {
+            write!(f, "\n  line {}: {}: {}", m.line, m.text, m.problem)?;
+        }
+        Ok(())
+    }
+}
+
+/// Runs the scenario `source` on the nodes of `shell`.
+pub fn run(shell: &mut Shell, source: &str) -> ScenarioReport {
+    let mut report = ScenarioReport::default();
+    for (i, line) in source.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let outcome = match line.strip_prefix("expect ") {
+            Some(expectation) => {
+                report.checks += 1;
+                check(shell, expectation)
+            }
+            None => {
+                report.commands += 1;
+                shell.execute(line).map(drop).map_err(|e| e.to_string())
+            }
+        };
+        if let Err(problem) = outcome {
+            report.mismatches.push(Mismatch {
+                line: i + 1,
+                text: line.to_string(),
+                problem,
+            });
+        }
+    }
+    report
+}
+
+/// Runs the scenario in the file at `path` on the nodes of `shell`.
+pub fn run_file(shell: &mut Shell, path: impl AsRef<Path>) -> Result<ScenarioReport> {
+    let path = path.as_ref();
+    let source = fs::read_to_string(path)
+        .io_context(|| format!("cannot read scenario {}", path.display()))?;
+    Ok(run(shell, &source))
+}
+
+/// Runs each scenario file on a ring of its own, printing its report, and
+/// returns whether all of them passed.
+pub fn run_files(paths: &[String]) -> bool {
+    let mut passed = true;
+    for path in paths {
+        match run_file(&mut Shell::new(), path) {
+            Ok(report) => {
+                println!("{}: {}", path, report);
+                passed &= report.passed();
+            }
+            Err(e) => {
+                println!("{}: {}", path, e);
+                passed = false;
+            }
+        }
+    }
+    passed
+}
+
+fn check(shell: &mut Shell, expectation: &str) -> std::result::Result<(), String> {
+    let words: Vec<&str> = expectation.split_whitespace().collect();
+    match words.as_slice() {
+        ["value", id, key, value @ ..] if !value.is_empty() => {
+            let expected = value.join(" ");
+            match find(shell, id, key)? {
+                Some(actual) if actual == expected => Ok(()),
+                Some(actual) => Err(format!("found {:?}, expected {:?}", actual, expected)),
+                None => Err(format!("found nothing, expected {:?}", expected)),
+            }
+        }
+        ["missing", id, key] => match find(shell, id, key)? {
+            Some(actual) => Err(format!("found {:?}, expected nothing", actual)),
+            None => Ok(()),
+        },
+        ["keys", id, keys @ ..] => {
+            let node = shell.node(id).map_err(|e| e.to_string())?;
+            let mut expected: Vec<u8> = keys.iter().map(|k| key_id(&node, k)).collect();
+            let mut held: Vec<u8> = node.keys().iter().map(|(k, _)| k.id).collect();
+            expected.sort();
+            held.sort();
+            if held == expected {
+                Ok(())
+            } else {
+                Err(format!(
+                    "node holds [{}], expected [{}]",
+                    join(&held),
+                    join(&expected)
+                ))
+            }
+        }
+        ["error", command @ ..] if !command.is_empty() => match shell.execute(&command.join(" ")) {
+            Ok(_) => Err("the command succeeded, expected an error".to_string()),
+            Err(_) => Ok(()),
+        },
+        _ => Err("unknown expectation".to_string()),
+    }
+}
+
+fn find(shell: &Shell, id: &str, key: &str) -> std::result::Result<Option<String>, String> {
+    let node = shell.node(id).map_err(|e| e.to_string())?;
+    let value = match key.parse::<u8>() {
+        Ok(k) => node.find(k),
+        Err(_) => node.find_key(key),
+    };
+    value.map_err(|e| e.to_string())
+}
+
+/// The identifier of a key as the shell reads it.
+fn key_id(node: &ShellNode, key: &str) -> u8 {
+    key.parse().unwrap_or_else(|_| node.hash_key(key).id)
+}
+
+fn join(ids: &[u8]) -> String {
+    let ids: Vec<String> = ids.iter().map(|id| id.to_string()).collect();
+    ids.join(", ")
+}
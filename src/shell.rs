@@ -0,0 +1,320 @@
+//! Interactive shell for exploring a ring of nodes in this process.
+//!
+//! Nodes call each other directly, as in the tests, on a ring of `2^8`
+//! identifiers. A key given as a number below 256 is used as its identifier;
+//! anything else is hashed, as by [`Node::insert_key`].
+
+use crate::error::{Error, IoContext};
+use crate::event::{Event, EventLog};
+use crate::node::{Lookup, Node};
+use crate::ring::Ring;
+use crate::scenario;
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::sync::Arc;
+use thiserror::Error;
+
+pub type ShellNode = Node<u8, String, 8>;
+
+const HELP: &str = "\
+commands:
+  create <id>                  create a node outside any ring
+  join <id> [<bootstrap>]      join the ring through <bootstrap>, or start a new one
+  leave <id>                   leave the ring, handing the keys to the successor
+  crash <id>                   stop the node without telling the others
+  tick [<rounds>]              run maintenance rounds on every live node
+  insert <node> <key> <value>  store a value, looking the key up from <node>
+  get <node> <key>             look a key up from <node>
+  delete <node> <key>          remove a key, looking it up from <node>
+  fingers <id>                 show the finger table of a node
+  keys <id>                    show the keys a node owns
+  lookups <id>                 show the lookups a node has made
+  events                       show what happened since the last `events`
+  ring                         show every node in ring order
+  check                        look for inconsistencies between the live nodes
+  export dot|json [<file>]     write the live nodes as a Graphviz graph or JSON
+  metrics                      show the counters of the live nodes
+  run <file>                   run a scenario file on these nodes
+  help                         show this help
+  quit                         leave the shell";
+
+const EXPORT_USAGE: &str = "export dot|json [<file>]";
+
+#[derive(Debug, Error)]
+pub enum ShellError {
+    #[error("usage: {0}")]
+    Usage(&'static str),
+    #[error("unknown command {0:?}, see `help`")]
+    UnknownCommand(String),
+    #[error("{0:?} is not a node identifier")]
+    InvalidId(String),
+    #[error("no node {0}")]
+    NoSuchNode(u8),
+    #[error("node {0} already exists")]
+    NodeExists(u8),
+    #[error(transparent)]
+    Node(#[from] Error),
+}
+
+/// The nodes created so far and the commands run on them.
+pub struct Shell {
+    ring: Ring<u8, String, 8>,
+    events: Arc<EventLog<u8>>,
+    // lookups taken out of `events`, kept for `lookups`
+    lookups: Vec<Event<u8>>,
+    finished: bool,
+}
+
+impl Shell {
+    pub fn new() -> Self {
+        let mut ring = Ring::new();
+        let events = Arc::new(EventLog::new());
+        ring.subscribe(events.clone());
+        Self {
+            ring,
+            events,
+            lookups: Vec::new(),
+            finished: false,
+        }
+    }
+
+    /// Whether `quit` was entered.
+    pub fn finished(&self) -> bool {
+        self.finished
+    }
+
+    /// Runs one command line and returns what it has to say, if anything.
+    ///
+    /// `fingers` and `keys` print straight to standard output.
+    pub fn execute(&mut self, line: &str) -> Result<String, ShellError> {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let Some((&command, args)) = words.split_first() else {
+            return Ok(String::new());
+        };
+        match (command, args) {
+            ("create", [id]) => {
+                let id = parse_id(id)?;
+                if self.ring.node(id).is_some_and(|n| n.is_alive()) {
+                    return Err(ShellError::NodeExists(id));
+                }
+                self.ring.create(id)?;
+                Ok(format!("created node {}", id))
+            }
+            ("create", _) => Err(ShellError::Usage("create <id>")),
+            ("join", [id]) => {
+                let id = self.node(id)?.id();
+                self.ring.join(id, None)?;
+                Ok(format!("node {} started a new ring", id))
+            }
+            ("join", [id, bootstrap]) => {
+                let bootstrap = self.node(bootstrap)?.id();
+                let id = self.node(id)?.id();
+                let id = self.ring.join(id, Some(bootstrap))?;
+                Ok(format!("node {} joined the ring", id))
+            }
+            ("join", _) => Err(ShellError::Usage("join <id> [<bootstrap>]")),
+            ("leave", [id]) => {
+                self.node(id)?.leave()?;
+                Ok(format!("node {} left the ring", id))
+            }
+            ("leave", _) => Err(ShellError::Usage("leave <id>")),
+            ("crash", [id]) => {
+                self.node(id)?.crash();
+                Ok(format!("node {} crashed", id))
+            }
+            ("crash", _) => Err(ShellError::Usage("crash <id>")),
+            ("tick", []) => Ok(self.tick(1)),
+            ("tick", [rounds]) => {
+                let rounds = rounds
+                    .parse()
+                    .map_err(|_| ShellError::Usage("tick [<rounds>]"))?;
+                Ok(self.tick(rounds))
+            }
+            ("tick", _) => Err(ShellError::Usage("tick [<rounds>]")),
+            ("insert", [id, key, value @ ..]) if !value.is_empty() => {
+                let mut node = self.node(id)?;
+                let value = value.join(" ");
+                match key.parse::<u8>() {
+                    Ok(k) => node.insert(k, value)?,
+                    Err(_) => node.insert_key(key, value)?,
+                }
+                Ok(format!("stored {}", key))
+            }
+            ("insert", _) => Err(ShellError::Usage("insert <node> <key> <value>")),
+            ("get", [id, key]) => {
+                let node = self.node(id)?;
+                let lookup = match key.parse::<u8>() {
+                    Ok(k) => node.lookup(k)?,
+                    Err(_) => node.lookup_key(key)?,
+                };
+                Ok(describe_lookup(key, &lookup))
+            }
+            ("get", _) => Err(ShellError::Usage("get <node> <key>")),
+            ("delete", [id, key]) => {
+                let mut node = self.node(id)?;
+                match key.parse::<u8>() {
+                    Ok(k) => node.remove(k)?,
+                    Err(_) => node.remove_key(key)?,
+                }
+                Ok(format!("deleted {}", key))
+            }
+            ("delete", _) => Err(ShellError::Usage("delete <node> <key>")),
+            ("fingers", [id]) => {
+                self.node(id)?.pretty_print();
+                Ok(String::new())
+            }
+            ("fingers", _) => Err(ShellError::Usage("fingers <id>")),
+            ("keys", [id]) => {
+                self.node(id)?.print_keys();
+                Ok(String::new())
+            }
+            ("keys", _) => Err(ShellError::Usage("keys <id>")),
+            ("lookups", [id]) => {
+                let id = self.node(id)?.id();
+                self.drain_events();
+                let lookups: Vec<String> = self
+                    .lookups
+                    .iter()
+                    .filter(|e| matches!(e, Event::LookupCompleted { node, .. } if *node == id))
+                    .map(|e| e.to_string())
+                    .collect();
+                Ok(lookups.join("\n"))
+            }
+            ("lookups", _) => Err(ShellError::Usage("lookups <id>")),
+            ("events", []) => {
+                let events: Vec<String> =
+                    self.drain_events().iter().map(|e| e.to_string()).collect();
+                Ok(events.join("\n"))
+            }
+            ("events", _) => Err(ShellError::Usage("events")),
+            ("ring", []) => Ok(self.dump_ring()),
+            ("ring", _) => Err(ShellError::Usage("ring")),
+            ("check", []) => Ok(self.ring.audit().to_string()),
+            ("check", _) => Err(ShellError::Usage("check")),
+            ("export", [format, path @ ..]) if path.len() <= 1 => {
+                let text = match *format {
+                    "dot" => self.ring.to_dot(),
+                    "json" => self.ring.to_json(),
+                    _ => return Err(ShellError::Usage(EXPORT_USAGE)),
+                };
+                match path {
+                    [path] => {
+                        fs::write(path, text).io_context(|| format!("cannot write {}", path))?;
+                        Ok(format!("wrote {}", path))
+                    }
+                    _ => Ok(text),
+                }
+            }
+            ("export", _) => Err(ShellError::Usage(EXPORT_USAGE)),
+            ("metrics", []) => Ok(self.ring.to_prometheus()),
+            ("metrics", _) => Err(ShellError::Usage("metrics")),
+            ("run", [path]) => Ok(scenario::run_file(self, path)?.to_string()),
+            ("run", _) => Err(ShellError::Usage("run <file>")),
+            ("help", _) => Ok(HELP.to_string()),
+            ("quit" | "exit", _) => {
+                self.finished = true;
+                Ok(String::new())
+            }
+            (other, _) => Err(ShellError::UnknownCommand(other.to_string())),
+        }
+    }
+
+    /// A handle to the node named by `id`.
+    pub(crate) fn node(&self, id: &str) -> Result<ShellNode, ShellError> {
+        let id = parse_id(id)?;
+        self.ring.node(id).ok_or(ShellError::NoSuchNode(id))
+    }
+
+    /// Takes the events recorded since the last call, keeping the lookups.
+    fn drain_events(&mut self) -> Vec<Event<u8>> {
+        let events = self.events.take();
+        self.lookups.extend(
+            events
+                .iter()
+                .filter(|e| matches!(e, Event::LookupCompleted { .. }))
+                .cloned(),
+        );
+        events
+    }
+
+    fn tick(&mut self, rounds: usize) -> String {
+        let failures = self.ring.tick(rounds);
+        format!("ran {} rounds, {} steps failed", rounds, failures)
+    }
+
+    /// One line per node: its state, neighbours and number of keys.
+    fn dump_ring(&self) -> String {
+        if self.ring.is_empty() {
+            return "no nodes".to_string();
+        }
+        let lines: Vec<String> = self
+            .ring
+            .nodes()
+            .map(|n| {
+                let predecessor = match n.predecessor_id() {
+                    Some(p) => p.to_string(),
+                    None => "-".to_string(),
+                };
+                let successors: Vec<String> =
+                    n.successor_list().iter().map(|s| s.to_string()).collect();
+                format!(
+                    "node {:>3} {:<5} pred {:>3} succ {:>3} list [{}] keys {}",
+                    n.id(),
+                    if n.is_alive() { "up" } else { "down" },
+                    predecessor,
+                    n.successor_id(),
+                    successors.join(", "),
+                    n.keys().len()
+                )
+            })
+            .collect();
+        lines.join("\n")
+    }
+}
+
+impl Default for Shell {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn parse_id(id: &str) -> Result<u8, ShellError> {
+    id.parse()
+        .map_err(|_| ShellError::InvalidId(id.to_string()))
+}
+
+fn describe_lookup(key: &str, lookup: &Lookup<u8, String>) -> String {
+    let path: Vec<String> = lookup.path.iter().map(|id| id.to_string()).collect();
+    let value = match &lookup.value {
+        Some(value) => format!("{:?}", value),
+        None => "not found".to_string(),
+    };
+    format!(
+        "{}: {} at node {} via [{}]",
+        key,
+        value,
+        lookup.owner,
+        path.join(", ")
+    )
+}
+
+/// Reads commands from standard input until `quit` or the end of input.
+pub fn run() -> io::Result<()> {
+    let mut shell = Shell::new();
+    let mut lines = io::stdin().lock().lines();
+    println!("chord shell, type `help` for the commands");
+    while !shell.finished() {
+        print!("chord> ");
+        io::stdout().flush()?;
+        let Some(line) = lines.next() else {
+            println!();
+            break;
+        };
+        match shell.execute(&line?) {
+            Ok(output) if output.is_empty() => {}
+            Ok(output) => println!("{}", output),
+            Err(e) => println!("error: {}", e),
+        }
+    }
+    Ok(())
+}
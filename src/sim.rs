@@ -0,0 +1,662 @@
+//! Deterministic discrete-event simulation of large rings.
+//!
+//! Every simulated node is a real [`Node`] reaching the others through a
+//! simulated network that delays, drops and partitions messages. Events —
+//! maintenance rounds, lookups, joins, leaves, crashes and partitions — are
+//! processed in virtual-time order, each running to completion before the
+//! next one starts, and every random choice is drawn from one seeded
+//! generator, so a run is fully determined by its [`SimConfig`].
+//!
+//! Message latency does not advance the clock: operations are treated as
+//! instantaneous, and the latency of their messages is summed to report how
+//! long they would have taken.
+
+use crate::config::Config;
+use crate::error::{Error, Result};
+use crate::id::Identifier;
+use crate::node::{Node, WeakNodeRef};
+use crate::rpc::{Request, Response, Transport};
+use crate::storage::MemoryStorage;
+use core::fmt;
+use rand::{seq::SliceRandom, Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use std::{
+    cmp::Reverse,
+    collections::{BTreeMap, BTreeSet, BinaryHeap, HashMap, HashSet},
+    sync::{Arc, Mutex, MutexGuard, PoisonError, Weak},
+    time::Duration,
+};
+
+/// A simulated node, storing each key under its own identifier.
+pub type SimNode<const M: u32> = Node<u64, u64, M>;
+
+/// Rates of membership changes, as expected events per virtual second.
+#[derive(Clone, Debug, Default)]
+pub struct Churn {
+    pub joins_per_sec: f64,
+    pub leaves_per_sec: f64,
+    pub crashes_per_sec: f64,
+}
+
+/// A partition cutting a random `fraction` of the live nodes off from the
+/// rest of the ring between `start` and `end`.
+#[derive(Clone, Debug)]
+pub struct Partition {
+    pub start: Duration,
+    pub end: Duration,
+    pub fraction: f64,
+}
+
+#[derive(Clone, Debug)]
+pub struct SimConfig {
+    /// Seed of every random choice; equal configurations give equal runs.
+    pub seed: u64,
+    /// Number of nodes in the ring when the simulation starts.
+    pub nodes: usize,
+    /// Number of keys inserted before the simulation starts.
+    pub keys: usize,
+    /// Virtual time simulated.
+    pub duration: Duration,
+    pub lookups_per_sec: f64,
+    /// Bounds of the uniformly distributed one-way message latency.
+    pub min_latency: Duration,
+    pub max_latency: Duration,
+    /// How long a sender waits for a lost message before giving up, unless
+    /// it is a lookup hop bounded by the nodes' `hop_timeout`.
+    pub timeout: Duration,
+    /// Probability that any one message is lost.
+    pub loss: f64,
+    pub churn: Churn,
+    pub partitions: Vec<Partition>,
+    /// Maintenance rounds run on the healed network at the end, before lost
+    /// keys are counted.
+    pub settle_rounds: usize,
+    /// Configuration of every node; its intervals schedule the maintenance.
+    pub node_config: Config,
+}
+
+impl Default for SimConfig {
+    fn default() -> Self {
+        Self {
+            seed: 0,
+            nodes: 100,
+            keys: 1000,
+            duration: Duration::from_secs(60),
+            lookups_per_sec: 10.0,
+            min_latency: Duration::from_millis(10),
+            max_latency: Duration::from_millis(50),
+            timeout: Duration::from_millis(500),
+            loss: 0.0,
+            churn: Churn::default(),
+            partitions: Vec::new(),
+            settle_rounds: 8,
+            node_config: Config::default(),
+        }
+    }
+}
+
+/// Outcome of a simulation.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SimReport {
+    /// Lookups issued, and how many of them failed or returned a wrong value.
+    pub lookups: usize,
+    pub failed_lookups: usize,
+    /// Number of successful lookups by their hop count.
+    pub hops: BTreeMap<usize, usize>,
+    /// Summed message latency of the successful lookups.
+    pub lookup_latency: Duration,
+    /// Messages sent, responses included, and how many of them were lost.
+    pub messages: u64,
+    pub lost_messages: u64,
+    pub joins: usize,
+    pub failed_joins: usize,
+    pub leaves: usize,
+    pub failed_leaves: usize,
+    pub crashes: usize,
+    /// Keys inserted, and how many of them no live node holds at the end.
+    pub keys: usize,
+    pub lost_keys: usize,
+    pub live_nodes: usize,
+}
+
+impl SimReport {
+    pub fn failure_rate(&self) -> f64 {
+        if self.lookups == 0 {
+            0.0
+        } else {
+            self.failed_lookups as f64 / self.lookups as f64
+        }
+    }
+
+    pub fn mean_hops(&self) -> f64 {
+        let succeeded: usize = self.hops.values().sum();
+        if succeeded == 0 {
+            return 0.0;
+        }
+        let total: usize = self.hops.iter().map(|(hops, n)| hops * n).sum();
+        total as f64 / succeeded as f64
+    }
+
+    pub fn mean_lookup_latency(&self) -> Duration {
+        let succeeded: usize = self.hops.values().sum();
+        if succeeded == 0 {
+            Duration::ZERO
+        } else {
+            self.lookup_latency / succeeded as u32
+        }
+    }
+}
+
+impl fmt::Display for SimReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "lookups: {} ({} failed, {:.2}%)",
+            self.lookups,
+            self.failed_lookups,
+            self.failure_rate() * 100.0
+        )?;
+        writeln!(
+            f,
+            "hops: mean {:.2}, mean latency {:?}",
+            self.mean_hops(),
+            self.mean_lookup_latency()
+        )?;
+        for (hops, n) in self.hops.iter() {
+            writeln!(f, "  {:>3}: {}", hops, n)?;
+        }
+        writeln!(
+            f,
+            "messages: {} ({} lost)",
+            self.messages, self.lost_messages
+        )?;
+        writeln!(
+            f,
+            "membership: {} joins ({} failed), {} leaves ({} failed), {} crashes, {} nodes left",
+            self.joins,
+            self.failed_joins,
+            self.leaves,
+            self.failed_leaves,
+            self.crashes,
+            self.live_nodes
+        )?;
+        write!(f, "keys: {} ({} lost)", self.keys, self.lost_keys)
+    }
+}
+
+struct NetworkState<const M: u32> {
+    nodes: HashMap<String, WeakNodeRef<u64, u64, M>>,
+    rng: ChaCha8Rng,
+    min_latency: Duration,
+    max_latency: Duration,
+    timeout: Duration,
+    loss: f64,
+    // active partitions by index, each holding the nodes it cuts off
+    partitions: BTreeMap<usize, HashSet<String>>,
+    messages: u64,
+    lost_messages: u64,
+    // latency of the messages sent since the last `start_timing`
+    elapsed: Duration,
+}
+
+/// The simulated network shared by all nodes.
+struct Network<const M: u32> {
+    state: Mutex<NetworkState<M>>,
+}
+
+impl<const M: u32> Network<M> {
+    fn state(&self) -> MutexGuard<'_, NetworkState<M>> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Sends one message from `from` to `to`, returning whether it arrived.
+    /// A lost message costs the sender `timeout`.
+    fn transmit(&self, from: &str, to: &str, timeout: Duration) -> bool {
+        let mut state = self.state();
+        let state = &mut *state;
+        state.messages += 1;
+        let cut = state
+            .partitions
+            .values()
+            .any(|p| p.contains(from) != p.contains(to));
+        if cut || (state.loss > 0.0 && state.rng.gen_bool(state.loss)) {
+            state.lost_messages += 1;
+            state.elapsed += timeout;
+            return false;
+        }
+        state.elapsed += state.rng.gen_range(state.min_latency..=state.max_latency);
+        true
+    }
+
+    fn start_timing(&self) {
+        self.state().elapsed = Duration::ZERO;
+    }
+
+    /// Latency of the messages sent since [`Network::start_timing`].
+    fn elapsed(&self) -> Duration {
+        self.state().elapsed
+    }
+}
+
+/// A node's connection to the simulated network.
+struct SimLink<const M: u32> {
+    address: String,
+    network: Arc<Network<M>>,
+}
+
+impl<const M: u32> Transport<u64, u64> for SimLink<M> {
+    fn call(&self, address: &str, request: Request<u64, u64>) -> Result<Response<u64, u64>> {
+        let timeout = self.network.state().timeout;
+        self.call_with_timeout(address, request, timeout)
+    }
+
+    fn call_with_timeout(
+        &self,
+        address: &str,
+        request: Request<u64, u64>,
+        timeout: Duration,
+    ) -> Result<Response<u64, u64>> {
+        if !self.network.transmit(&self.address, address, timeout) {
+            return Err(Error::Timeout {
+                address: address.to_string(),
+            });
+        }
+        let node_inner = self
+            .network
+            .state()
+            .nodes
+            .get(address)
+            .and_then(Weak::upgrade)
+            .ok_or_else(|| Error::NodeUnreachable {
+                address: address.to_string(),
+                reason: "no such node".to_string(),
+            })?;
+        let mut node = Node::new_inner(node_inner);
+        if !node.is_alive() && !request.is_answered_outside_ring() {
+            self.network.state().elapsed += timeout;
+            return Err(Error::Timeout {
+                address: address.to_string(),
+            });
+        }
+        let response = node.handle(request);
+        if !self.network.transmit(address, &self.address, timeout) {
+            return Err(Error::Timeout {
+                address: address.to_string(),
+            });
+        }
+        Ok(response)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+enum Event {
+    Maintain(usize),
+    FixFingers(usize),
+    Lookup,
+    Join,
+    Leave,
+    Crash,
+    PartitionStart(usize),
+    PartitionEnd(usize),
+}
+
+/// A simulation run; see the module documentation.
+pub struct Simulation<const M: u32> {
+    config: SimConfig,
+    rng: ChaCha8Rng,
+    network: Arc<Network<M>>,
+    // every node ever created, in creation order
+    nodes: Vec<SimNode<M>>,
+    ids: HashSet<u64>,
+    // inserted keys, each stored with itself as value
+    keys: Vec<u64>,
+    // pending events by time, ties broken by scheduling order
+    events: BinaryHeap<Reverse<(Duration, u64, Event)>>,
+    scheduled: u64,
+    now: Duration,
+    report: SimReport,
+}
+
+impl<const M: u32> Simulation<M> {
+    pub fn new(config: SimConfig) -> Result<Self> {
+        if M == 0 || M > u64::BITS {
+            return Err(Error::InvalidConfig(format!(
+                "identifiers of {} bits do not fit in u64",
+                M
+            )));
+        }
+        if config.nodes == 0 {
+            return Err(Error::InvalidConfig(
+                "the ring needs at least one node".to_string(),
+            ));
+        }
+        if M < u64::BITS && config.nodes as u64 > 1 << (M - 1) {
+            return Err(Error::InvalidConfig(format!(
+                "{} nodes do not fit on a ring of 2^{} identifiers",
+                config.nodes, M
+            )));
+        }
+        if M < u64::BITS && config.keys as u64 > 1 << M {
+            return Err(Error::InvalidConfig(format!(
+                "{} keys do not fit on a ring of 2^{} identifiers",
+                config.keys, M
+            )));
+        }
+        if !(0.0..=1.0).contains(&config.loss) {
+            return Err(Error::InvalidConfig(format!(
+                "loss must be a probability, got {}",
+                config.loss
+            )));
+        }
+        if config
+            .partitions
+            .iter()
+            .any(|p| !(0.0..=1.0).contains(&p.fraction))
+        {
+            return Err(Error::InvalidConfig(
+                "partition fractions must lie between 0 and 1".to_string(),
+            ));
+        }
+        if config.min_latency > config.max_latency {
+            return Err(Error::InvalidConfig(
+                "min_latency exceeds max_latency".to_string(),
+            ));
+        }
+        if config.node_config.stabilize_interval.is_zero()
+            || config.node_config.fix_fingers_interval.is_zero()
+        {
+            return Err(Error::InvalidConfig(
+                "maintenance intervals must not be zero".to_string(),
+            ));
+        }
+        let mut rng = ChaCha8Rng::seed_from_u64(config.seed);
+        let network = Network {
+            state: Mutex::new(NetworkState {
+                nodes: HashMap::new(),
+                rng: ChaCha8Rng::seed_from_u64(rng.gen()),
+                min_latency: config.min_latency,
+                max_latency: config.max_latency,
+                timeout: config.timeout,
+                // faults start once the initial ring is built
+                loss: 0.0,
+                partitions: BTreeMap::new(),
+                messages: 0,
+                lost_messages: 0,
+                elapsed: Duration::ZERO,
+            }),
+        };
+        Ok(Self {
+            config,
+            rng,
+            network: Arc::new(network),
+            nodes: Vec::new(),
+            ids: HashSet::new(),
+            keys: Vec::new(),
+            events: BinaryHeap::new(),
+            scheduled: 0,
+            now: Duration::ZERO,
+            report: SimReport::default(),
+        })
+    }
+
+    /// Builds the initial ring, stores the keys, runs every event up to
+    /// `duration` and reports the outcome.
+    pub fn run(mut self) -> Result<SimReport> {
+        self.build_ring()?;
+        self.insert_keys()?;
+
+        self.network.state().loss = self.config.loss;
+        for i in 0..self.nodes.len() {
+            self.schedule_maintenance(i);
+        }
+        let churn = self.config.churn.clone();
+        self.schedule_at_rate(Event::Lookup, self.config.lookups_per_sec);
+        self.schedule_at_rate(Event::Join, churn.joins_per_sec);
+        self.schedule_at_rate(Event::Leave, churn.leaves_per_sec);
+        self.schedule_at_rate(Event::Crash, churn.crashes_per_sec);
+        for (i, p) in self.config.partitions.clone().into_iter().enumerate() {
+            self.schedule(p.start, Event::PartitionStart(i));
+            self.schedule(p.end, Event::PartitionEnd(i));
+        }
+        while let Some(Reverse((time, _, event))) = self.events.pop() {
+            if time > self.config.duration {
+                break;
+            }
+            self.now = time;
+            self.process(event);
+        }
+
+        // heal the network and let the ring repair before counting lost keys
+        {
+            let mut state = self.network.state();
+            state.loss = 0.0;
+            state.partitions.clear();
+        }
+        for _ in 0..self.config.settle_rounds {
+            for node in self.nodes.iter_mut() {
+                let _ = node.tick();
+            }
+        }
+        self.count_lost_keys();
+        {
+            let state = self.network.state();
+            self.report.messages = state.messages;
+            self.report.lost_messages = state.lost_messages;
+        }
+        Ok(self.report)
+    }
+
+    fn build_ring(&mut self) -> Result<()> {
+        for _ in 0..self.config.nodes {
+            let bootstrap = self.random_live_node();
+            let i = self
+                .add_node()
+                .ok_or_else(|| Error::InvalidConfig("no free identifier left".to_string()))?;
+            let bootstrap = bootstrap.map(|b| self.nodes[b].info()).transpose()?;
+            self.nodes[i].handle(Request::Join(bootstrap)).into_ok()?;
+        }
+        // fill the successor lists and replicas
+        for _ in 0..=self.config.node_config.successor_list_len {
+            for node in self.nodes.iter_mut() {
+                node.tick()?;
+            }
+        }
+        Ok(())
+    }
+
+    fn insert_keys(&mut self) -> Result<()> {
+        let mut keys = BTreeSet::new();
+        while keys.len() < self.config.keys {
+            keys.insert(self.random_id());
+        }
+        for key in keys {
+            let i = self.random_live_node().expect("the ring is not empty");
+            self.nodes[i].insert(key, key)?;
+            self.keys.push(key);
+        }
+        self.report.keys = self.keys.len();
+        Ok(())
+    }
+
+    fn process(&mut self, event: Event) {
+        match event {
+            Event::Maintain(i) => {
+                if self.nodes[i].is_alive() {
+                    let _ = self.nodes[i].maintain();
+                    let interval = self.config.node_config.stabilize_interval;
+                    self.schedule(self.now + interval, event);
+                }
+            }
+            Event::FixFingers(i) => {
+                if self.nodes[i].is_alive() {
+                    let _ = self.nodes[i].fix_fingers();
+                    let interval = self.config.node_config.fix_fingers_interval;
+                    self.schedule(self.now + interval, event);
+                }
+            }
+            Event::Lookup => {
+                self.schedule_at_rate(event, self.config.lookups_per_sec);
+                self.lookup();
+            }
+            Event::Join => {
+                self.schedule_at_rate(event, self.config.churn.joins_per_sec);
+                self.join();
+            }
+            Event::Leave => {
+                self.schedule_at_rate(event, self.config.churn.leaves_per_sec);
+                if let Some(i) = self.random_survivor() {
+                    match self.nodes[i].leave() {
+                        Ok(()) => self.report.leaves += 1,
+                        Err(_) => self.report.failed_leaves += 1,
+                    }
+                }
+            }
+            Event::Crash => {
+                self.schedule_at_rate(event, self.config.churn.crashes_per_sec);
+                if let Some(i) = self.random_survivor() {
+                    self.nodes[i].crash();
+                    self.report.crashes += 1;
+                }
+            }
+            Event::PartitionStart(p) => {
+                let fraction = self.config.partitions[p].fraction;
+                let mut cut = HashSet::new();
+                for node in self.nodes.iter().filter(|n| n.is_alive()) {
+                    if self.rng.gen_bool(fraction) {
+                        cut.insert(node.info().expect("simulated nodes have addresses").address);
+                    }
+                }
+                self.network.state().partitions.insert(p, cut);
+            }
+            Event::PartitionEnd(p) => {
+                self.network.state().partitions.remove(&p);
+            }
+        }
+    }
+
+    fn lookup(&mut self) {
+        let Some(&key) = self.keys.choose(&mut self.rng) else {
+            return;
+        };
+        let Some(i) = self.random_live_node() else {
+            return;
+        };
+        self.network.start_timing();
+        let found = self.nodes[i].lookup(key);
+        let latency = self.network.elapsed();
+        self.report.lookups += 1;
+        match found {
+            Ok(lookup) if lookup.value == Some(key) => {
+                *self.report.hops.entry(lookup.hops()).or_insert(0) += 1;
+                self.report.lookup_latency += latency;
+            }
+            _ => self.report.failed_lookups += 1,
+        }
+    }
+
+    fn join(&mut self) {
+        let Some(bootstrap) = self.random_live_node() else {
+            return;
+        };
+        let Some(i) = self.add_node() else {
+            return;
+        };
+        let bootstrap = self.nodes[bootstrap].info().ok();
+        let joined = self.nodes[i]
+            .handle(Request::JoinStabilized(bootstrap))
+            .into_ok();
+        if joined.is_ok() {
+            self.report.joins += 1;
+            self.schedule_maintenance(i);
+        } else {
+            // a node that failed to join is abandoned
+            self.nodes[i].crash();
+            self.report.failed_joins += 1;
+        }
+    }
+
+    /// Creates a node with a fresh identifier, returning its index.
+    fn add_node(&mut self) -> Option<usize> {
+        if M < u64::BITS && self.ids.len() as u64 >= 1 << (M - 1) {
+            return None;
+        }
+        let id = loop {
+            let id = self.random_id();
+            if self.ids.insert(id) {
+                break id;
+            }
+        };
+        let address = format!("sim-{}", id);
+        let link = SimLink {
+            address: address.clone(),
+            network: self.network.clone(),
+        };
+        let node = Node::with_transport(
+            id,
+            address.clone(),
+            self.config.node_config.clone(),
+            Box::new(MemoryStorage::new()),
+            Arc::new(link),
+        );
+        self.network
+            .state()
+            .nodes
+            .insert(address, Arc::downgrade(&node.node_inner));
+        self.nodes.push(node);
+        Some(self.nodes.len() - 1)
+    }
+
+    fn random_id(&mut self) -> u64 {
+        u64::from_digest(&self.rng.gen::<u64>().to_be_bytes(), M)
+    }
+
+    fn random_live_node(&mut self) -> Option<usize> {
+        let live: Vec<usize> = (0..self.nodes.len())
+            .filter(|&i| self.nodes[i].is_alive())
+            .collect();
+        live.choose(&mut self.rng).copied()
+    }
+
+    /// A random live node whose departure leaves at least one node behind.
+    fn random_survivor(&mut self) -> Option<usize> {
+        let live = self.nodes.iter().filter(|n| n.is_alive()).count();
+        if live > 1 {
+            self.random_live_node()
+        } else {
+            None
+        }
+    }
+
+    fn schedule(&mut self, time: Duration, event: Event) {
+        self.scheduled += 1;
+        self.events.push(Reverse((time, self.scheduled, event)));
+    }
+
+    /// Schedules the next `event` of a Poisson process with `rate` per second.
+    fn schedule_at_rate(&mut self, event: Event, rate: f64) {
+        if rate > 0.0 {
+            let delay = -(1.0 - self.rng.gen::<f64>()).ln() / rate;
+            self.schedule(self.now + Duration::from_secs_f64(delay), event);
+        }
+    }
+
+    /// Starts the periodic maintenance of node `i` at random offsets, so that
+    /// nodes do not all act at once.
+    fn schedule_maintenance(&mut self, i: usize) {
+        let stabilize = self.config.node_config.stabilize_interval;
+        let fix_fingers = self.config.node_config.fix_fingers_interval;
+        let offset = self.rng.gen_range(Duration::ZERO..stabilize);
+        self.schedule(self.now + offset, Event::Maintain(i));
+        let offset = self.rng.gen_range(Duration::ZERO..fix_fingers);
+        self.schedule(self.now + offset, Event::FixFingers(i));
+    }
+
+    fn count_lost_keys(&mut self) {
+        let mut held = HashSet::new();
+        for node in self.nodes.iter().filter(|n| n.is_alive()) {
+            held.extend(node.keys().into_iter().map(|(k, _)| k.id));
+        }
+        self.report.lost_keys = self.keys.iter().filter(|k| !held.contains(*k)).count();
+        self.report.live_nodes = self.nodes.iter().filter(|n| n.is_alive()).count();
+    }
+}
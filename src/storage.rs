@@ -0,0 +1,191 @@
+use crate::error::{IoContext, Result};
+use crate::hash::Key;
+use crate::id::{in_interval, Identifier};
+use serde::{de::DeserializeOwned, Serialize};
+use std::{
+    collections::BTreeMap,
+    fs,
+    ops::Bound::{Excluded, Unbounded},
+    path::{Path, PathBuf},
+};
+
+/// Backend holding the keys a node is responsible for.
+///
+/// Reads are infallible: backends keep an in-memory view of their entries and
+/// only writes may fail. Ranges are ring intervals `(start, end]`, where
+/// `start == end` covers the whole ring.
+pub trait Storage<I, V>: Send {
+    fn get(&self, key: &Key<I>) -> Option<V>;
+
+    fn put(&mut self, key: Key<I>, value: V) -> Result<()>;
+
+    fn delete(&mut self, key: &Key<I>) -> Result<Option<V>>;
+
+    /// Returns the entries whose identifier lies in `(start, end]`, in ring order from `start`.
+    fn range(&self, start: I, end: I) -> Vec<(Key<I>, V)>;
+
+    /// Removes and returns the entries whose identifier lies in `(start, end]`.
+    fn drain_range(&mut self, start: I, end: I) -> Result<Vec<(Key<I>, V)>>;
+
+    fn len(&self) -> usize;
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// Keeps every entry in a `BTreeMap` ordered by identifier.
+pub struct MemoryStorage<I, V> {
+    entries: BTreeMap<Key<I>, V>,
+}
+
+impl<I: Identifier, V: Clone + Send> MemoryStorage<I, V> {
+    pub fn new() -> Self {
+        Self {
+            entries: BTreeMap::new(),
+        }
+    }
+
+    fn keys_in(&self, start: I, end: I) -> Vec<Key<I>> {
+        let lower = Key::from_id(start);
+        if start < end {
+            // ids equal to `end` may carry a name, so scan up to the next id
+            self.entries
+                .range((Excluded(&lower), Unbounded))
+                .map(|(k, _)| k)
+                .skip_while(|k| k.id == start)
+                .take_while(|k| k.id <= end)
+                .cloned()
+                .collect()
+        } else {
+            // walk the wrapped interval in ring order: (start, max] then [0, end]
+            let (high, low): (Vec<_>, Vec<_>) = self
+                .entries
+                .keys()
+                .filter(|k| in_interval(k.id, start, end))
+                .cloned()
+                .partition(|k| k.id > start);
+            high.into_iter().chain(low).collect()
+        }
+    }
+}
+
+impl<I: Identifier, V: Clone + Send> Default for MemoryStorage<I, V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<I: Identifier, V: Clone + Send> Storage<I, V> for MemoryStorage<I, V> {
+    fn get(&self, key: &Key<I>) -> Option<V> {
+        self.entries.get(key).cloned()
+    }
+
+    fn put(&mut self, key: Key<I>, value: V) -> Result<()> {
+        self.entries.insert(key, value);
+        Ok(())
+    }
+
+    fn delete(&mut self, key: &Key<I>) -> Result<Option<V>> {
+        Ok(self.entries.remove(key))
+    }
+
+    fn range(&self, start: I, end: I) -> Vec<(Key<I>, V)> {
+        self.keys_in(start, end)
+            .into_iter()
+            .map(|k| {
+                let v = self.entries[&k].clone();
+                (k, v)
+            })
+            .collect()
+    }
+
+    fn drain_range(&mut self, start: I, end: I) -> Result<Vec<(Key<I>, V)>> {
+        Ok(self
+            .keys_in(start, end)
+            .into_iter()
+            .filter_map(|k| self.entries.remove(&k).map(|v| (k, v)))
+            .collect())
+    }
+
+    fn len(&self) -> usize {
+        self.entries.len()
+    }
+}
+
+/// Persists every entry to a single JSON file, rewritten atomically on each change.
+pub struct FileStorage<I, V> {
+    path: PathBuf,
+    cache: MemoryStorage<I, V>,
+}
+
+impl<I, V> FileStorage<I, V>
+where
+    I: Identifier + Serialize + DeserializeOwned,
+    V: Clone + Send + Serialize + DeserializeOwned,
+{
+    /// Opens the store at `path`, loading any entries already written there.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let mut cache = MemoryStorage::new();
+        if path.exists() {
+            let data = fs::read(&path)
+                .io_context(|| format!("cannot read storage file {}", path.display()))?;
+            let entries: Vec<(Key<I>, V)> = serde_json::from_slice(&data)
+                .io_context(|| format!("corrupt storage file {}", path.display()))?;
+            cache.entries.extend(entries);
+        }
+        Ok(Self { path, cache })
+    }
+
+    fn flush(&self) -> Result<()> {
+        let entries: Vec<(&Key<I>, &V)> = self.cache.entries.iter().collect();
+        let tmp = self.path.with_extension("tmp");
+        let data = serde_json::to_vec(&entries)
+            .io_context(|| format!("cannot encode storage file {}", tmp.display()))?;
+        fs::write(&tmp, data)
+            .io_context(|| format!("cannot write storage file {}", tmp.display()))?;
+        fs::rename(&tmp, &self.path)
+            .io_context(|| format!("cannot replace storage file {}", self.path.display()))?;
+        Ok(())
+    }
+}
+
+impl<I, V> Storage<I, V> for FileStorage<I, V>
+where
+    I: Identifier + Serialize + DeserializeOwned,
+    V: Clone + Send + Serialize + DeserializeOwned,
+{
+    fn get(&self, key: &Key<I>) -> Option<V> {
+        self.cache.get(key)
+    }
+
+    fn put(&mut self, key: Key<I>, value: V) -> Result<()> {
+        self.cache.put(key, value)?;
+        self.flush()
+    }
+
+    fn delete(&mut self, key: &Key<I>) -> Result<Option<V>> {
+        let value = self.cache.delete(key)?;
+        if value.is_some() {
+            self.flush()?;
+        }
+        Ok(value)
+    }
+
+    fn range(&self, start: I, end: I) -> Vec<(Key<I>, V)> {
+        self.cache.range(start, end)
+    }
+
+    fn drain_range(&mut self, start: I, end: I) -> Result<Vec<(Key<I>, V)>> {
+        let drained = self.cache.drain_range(start, end)?;
+        if !drained.is_empty() {
+            self.flush()?;
+        }
+        Ok(drained)
+    }
+
+    fn len(&self) -> usize {
+        self.cache.len()
+    }
+}
@@ -1,7 +1,44 @@
 #[cfg(test)]
 mod tests {
 
-    use super::super::node::Node;
+    use super::super::audit::{audit, Violation};
+    use super::super::config::{Config, IdCollision, LookupMode};
+    use super::super::durable::DurableStorage;
+    use super::super::error::Error;
+    use super::super::event::{Event, EventLog};
+    #[cfg(feature = "shell")]
+    use super::super::export::RingSnapshot;
+    use super::super::hash::{HashFunction, Key};
+    use super::super::id::{Identifier, U160};
+    use super::super::metrics::{self, serve_metrics, HOP_BUCKETS};
+    use super::super::net::{serve, TcpTransport};
+    use super::super::node;
+    use super::super::ring::Ring;
+    use super::super::rpc::{MemoryTransport, Request, Response};
+    #[cfg(feature = "async")]
+    use super::super::runtime::AsyncNode;
+    #[cfg(feature = "shell")]
+    use super::super::scenario::{self, Mismatch};
+    #[cfg(feature = "shell")]
+    use super::super::shell::{Shell, ShellError};
+    use super::super::sim::{Churn, Partition, SimConfig, Simulation};
+    use super::super::storage::{FileStorage, MemoryStorage, Storage};
+    #[cfg(feature = "async")]
+    use std::time::Instant;
+    use std::{
+        io::{Read, Write},
+        net::{TcpListener, TcpStream},
+        sync::{
+            atomic::{AtomicBool, Ordering},
+            Arc, Mutex,
+        },
+        thread,
+        time::Duration,
+    };
+
+    type Node = node::Node<u8, Option<u8>, 8>;
+    type RemoteNode = node::RemoteNode<u8, Option<u8>, 8>;
+
     #[test]
     fn test_sample_case() {
         let mut n0 = Node::new(0);
@@ -10,12 +47,12 @@ mod tests {
         let mut n3 = Node::new(110);
         let mut n4 = Node::new(160);
         let mut n5 = Node::new(230);
-        n0.join(None);
-        n1.join(Some(n0.clone()));
-        n2.join(Some(n1.clone()));
-        n3.join(Some(n2.clone()));
-        n4.join(Some(n3.clone()));
-        n5.join(Some(n4.clone()));
+        n0.join(None).unwrap();
+        n1.join(Some(n0.clone())).unwrap();
+        n2.join(Some(n1.clone())).unwrap();
+        n3.join(Some(n2.clone())).unwrap();
+        n4.join(Some(n3.clone())).unwrap();
+        n5.join(Some(n4.clone())).unwrap();
         n0.pretty_print();
         n1.pretty_print();
         n2.pretty_print();
@@ -23,18 +60,18 @@ mod tests {
         n4.pretty_print();
         n5.pretty_print();
 
-        n0.insert(3, Some(3));
-        n1.insert(200, None);
-        n2.insert(123, None);
-        n3.insert(45, Some(3));
-        n4.insert(99, None);
-        n2.insert(60, Some(10));
-        n0.insert(50, Some(8));
-        n3.insert(100, Some(5));
-        n3.insert(101, Some(4));
-        n3.insert(102, Some(6));
-        n5.insert(240, Some(8));
-        n5.insert(250, Some(10));
+        n0.insert(3, Some(3)).unwrap();
+        n1.insert(200, None).unwrap();
+        n2.insert(123, None).unwrap();
+        n3.insert(45, Some(3)).unwrap();
+        n4.insert(99, None).unwrap();
+        n2.insert(60, Some(10)).unwrap();
+        n0.insert(50, Some(8)).unwrap();
+        n3.insert(100, Some(5)).unwrap();
+        n3.insert(101, Some(4)).unwrap();
+        n3.insert(102, Some(6)).unwrap();
+        n5.insert(240, Some(8)).unwrap();
+        n5.insert(250, Some(10)).unwrap();
 
         n0.print_keys();
         n1.print_keys();
@@ -43,60 +80,1679 @@ mod tests {
         n4.print_keys();
         n5.print_keys();
 
+        let events = Arc::new(EventLog::new());
         let mut n6 = Node::new(100);
-        n6.join(Some(n5.clone()));
+        for n in [&n0, &n1, &n2, &n3, &n4, &n5, &n6] {
+            n.subscribe(events.clone());
+        }
+        n6.join(Some(n5.clone())).unwrap();
         n3.print_keys();
         n6.print_keys();
+        assert_eq!(migrations(&events), [(99, 110, 100), (100, 110, 100)]);
+
+        n0.find(3).unwrap();
+        n0.find(200).unwrap();
+        n0.find(123).unwrap();
+        n0.find(45).unwrap();
+        n0.find(99).unwrap();
+        n0.find(60).unwrap();
+        n0.find(50).unwrap();
+        n0.find(100).unwrap();
+        n0.find(101).unwrap();
+        n0.find(102).unwrap();
+        n0.find(240).unwrap();
+        n0.find(250).unwrap();
+
+        n2.find(3).unwrap();
+        n2.find(200).unwrap();
+        n2.find(123).unwrap();
+        n2.find(45).unwrap();
+        n2.find(99).unwrap();
+        n2.find(60).unwrap();
+        n2.find(50).unwrap();
+        n2.find(100).unwrap();
+        n2.find(101).unwrap();
+        n2.find(102).unwrap();
+        n2.find(240).unwrap();
+        n2.find(250).unwrap();
+
+        n6.find(3).unwrap();
+        n6.find(200).unwrap();
+        n6.find(123).unwrap();
+        n6.find(45).unwrap();
+        n6.find(99).unwrap();
+        n6.find(60).unwrap();
+        n6.find(50).unwrap();
+        n6.find(100).unwrap();
+        n6.find(101).unwrap();
+        n6.find(102).unwrap();
+        n6.find(240).unwrap();
+        n6.find(250).unwrap();
+
+        // every lookup is reported by the node it started from
+        let lookups: Vec<Event<u8>> = events
+            .take()
+            .into_iter()
+            .filter(|e| matches!(e, Event::LookupCompleted { .. }))
+            .collect();
+        assert_eq!(lookups.len(), 36);
+        assert!(lookups.contains(&Event::LookupCompleted {
+            node: 0,
+            key: Key::from_id(200),
+            path: vec![0, 160, 230],
+            owner: 230,
+            found: true,
+        }));
 
-        n0.find(3);
-        n0.find(200);
-        n0.find(123);
-        n0.find(45);
-        n0.find(99);
-        n0.find(60);
-        n0.find(50);
-        n0.find(100);
-        n0.find(101);
-        n0.find(102);
-        n0.find(240);
-        n0.find(250);
-
-        n2.find(3);
-        n2.find(200);
-        n2.find(123);
-        n2.find(45);
-        n2.find(99);
-        n2.find(60);
-        n2.find(50);
-        n2.find(100);
-        n2.find(101);
-        n2.find(102);
-        n2.find(240);
-        n2.find(250);
-
-        n6.find(3);
-        n6.find(200);
-        n6.find(123);
-        n6.find(45);
-        n6.find(99);
-        n6.find(60);
-        n6.find(50);
-        n6.find(100);
-        n6.find(101);
-        n6.find(102);
-        n6.find(240);
-        n6.find(250);
-
-        n0.print_lookup_results();
-        n2.print_lookup_results();
-        n6.print_lookup_results();
-
-        n2.leave();
+        n2.leave().unwrap();
+        assert_eq!(
+            migrations(&events),
+            [(45, 65, 100), (50, 65, 100), (60, 65, 100)]
+        );
+        assert!(events.events().contains(&Event::NodeLeft { node: 65 }));
 
         n0.pretty_print();
         n1.pretty_print();
 
         n0.print_keys();
         n1.print_keys();
+        assert_healthy([&n0, &n1, &n2, &n3, &n4, &n5, &n6]);
+    }
+
+    /// The keys moved so far, as `(key, from, to)`.
+    fn migrations(events: &EventLog<u8>) -> Vec<(u8, u8, u8)> {
+        events
+            .events()
+            .into_iter()
+            .filter_map(|e| match e {
+                Event::KeyMigrated { key, from, to } => Some((key.id, from, to)),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_events() {
+        let joined = Arc::new(Mutex::new(Vec::new()));
+        let log = Arc::new(EventLog::new());
+        let mut ring: Ring<u8, u8, 8> = Ring::new();
+        ring.subscribe(log.clone());
+        let mut n0 = ring.create(0).unwrap();
+        let seen = joined.clone();
+        n0.subscribe(Arc::new(move |e: &Event<u8>| {
+            if let Event::NodeJoined { node } = e {
+                seen.lock().unwrap().push(*node);
+            }
+        }));
+        n0.join_stabilized(None).unwrap();
+        let mut n1 = ring.create(100).unwrap();
+        n1.join_stabilized(Some(n0.clone())).unwrap();
+        // a joining node points every finger at its successor
+        let (fingers, others): (Vec<Event<u8>>, Vec<Event<u8>>) = log
+            .take()
+            .into_iter()
+            .partition(|e| matches!(e, Event::FingerUpdated { finger: 0, .. }));
+        assert_eq!(fingers.len(), 2 * 8);
+        assert_eq!(
+            others,
+            [
+                Event::NodeJoined { node: 0 },
+                Event::NodeJoined { node: 100 }
+            ]
+        );
+
+        // stabilization links the two nodes up over two rounds
+        ring.tick(2);
+        let events = log.take();
+        assert!(events.contains(&Event::PredecessorChanged {
+            node: 0,
+            predecessor: Some(100)
+        }));
+        assert!(events.contains(&Event::FingerUpdated {
+            node: 0,
+            index: 1,
+            finger: 100
+        }));
+        ring.tick(8);
+        assert!(!log.take().iter().any(|e| matches!(
+            e,
+            Event::PredecessorChanged { .. } | Event::FingerUpdated { index: 1, .. }
+        )));
+
+        ring.insert(50, 5).unwrap();
+        ring.leave(100).unwrap();
+        let events = log.take();
+        assert!(events.contains(&Event::KeyMigrated {
+            key: Key::from_id(50),
+            from: 100,
+            to: 0
+        }));
+        assert_eq!(events.last(), Some(&Event::NodeLeft { node: 100 }));
+        // the closure only hears from the node it was subscribed to
+        assert_eq!(*joined.lock().unwrap(), [0]);
+        assert_healthy(ring.nodes());
+    }
+
+    #[test]
+    fn test_lookup_paths() {
+        let mut ring: Vec<Node> = [0, 30, 65, 110, 160, 230].map(Node::new).into();
+        join_all(&mut ring);
+        ring[0].insert(200, Some(2)).unwrap();
+        ring[0].insert(20, Some(1)).unwrap();
+
+        // node 0 jumps straight to its last finger, 160, whose successor owns 200
+        let lookup = ring[0].lookup(200).unwrap();
+        assert_eq!(lookup.path, vec![0, 160, 230]);
+        assert_eq!(lookup.owner, 230);
+        assert_eq!(lookup.value, Some(Some(2)));
+        assert_eq!(lookup.hops(), 2);
+
+        let lookup = ring[0].lookup(20).unwrap();
+        assert_eq!(lookup.path, vec![0, 30]);
+        assert_eq!(lookup.hops(), 1);
+
+        let lookup = ring[1].lookup(100).unwrap();
+        assert_eq!(lookup.path, vec![30, 65, 110]);
+        assert_eq!(lookup.owner, 110);
+        assert_eq!(lookup.value, None);
+
+        for n in ring.iter() {
+            let lookup = n.lookup(200).unwrap();
+            assert_eq!(lookup.path.first(), Some(&n.id()));
+            assert_eq!(lookup.path.last(), Some(&230));
+            assert!(lookup.hops() <= 3);
+        }
+        assert_healthy(&ring);
+    }
+
+    #[test]
+    fn test_wide_identifier_spaces() {
+        let ids = [7u32, 1 << 20, 3 << 28, u32::MAX - 5];
+        let mut ring: Vec<node::Node<u32, u8, 32>> =
+            ids.iter().map(|id| node::Node::new(*id)).collect();
+        join_all(&mut ring);
+        let keys = [0u32, 8, 1 << 20, (1 << 20) + 1, u32::MAX];
+        for (value, key) in keys.iter().enumerate() {
+            let len = ring.len();
+            ring[value % len].insert(*key, value as u8).unwrap();
+        }
+        for n in ring.iter() {
+            for (value, key) in keys.iter().enumerate() {
+                assert_eq!(n.find(*key).unwrap(), Some(value as u8));
+            }
+        }
+        assert_healthy(&ring);
+
+        let ids = [
+            U160::from(1),
+            U160::from(u128::MAX),
+            U160::from_be_bytes([0xff; 20]),
+        ];
+        let mut ring: Vec<node::Node<U160, u8, 160>> =
+            ids.iter().map(|id| node::Node::new(*id)).collect();
+        join_all(&mut ring);
+        let keys = [
+            U160::from(0),
+            U160::from(2),
+            U160::from(u128::MAX),
+            U160::from_be_bytes([0xee; 20]),
+        ];
+        for (value, key) in keys.iter().enumerate() {
+            ring[0].insert(*key, value as u8).unwrap();
+        }
+        for n in ring.iter() {
+            for (value, key) in keys.iter().enumerate() {
+                assert_eq!(n.find(*key).unwrap(), Some(value as u8));
+            }
+        }
+        assert_healthy(&ring);
+    }
+
+    #[test]
+    fn test_hashed_keys_and_addresses() {
+        let empty: U160 = HashFunction::Sha1.hash(b"", 160);
+        assert_eq!(
+            empty.to_string(),
+            "da39a3ee5e6b4b0d3255bfef95601890afd80709"
+        );
+        assert_eq!(HashFunction::Sha1.hash::<u8>(b"", 8), 0x09);
+        assert_eq!(HashFunction::Sha1.hash::<u8>(b"", 4), 0x09);
+        assert_eq!(HashFunction::Sha1.hash::<u16>(b"", 12), 0x709);
+
+        let key = Key::<u8>::from_name(b"apple", HashFunction::Sha256, 8);
+        assert_eq!(key.name, b"apple");
+        assert_eq!(key.id, HashFunction::Sha256.hash::<u8>(b"apple", 8));
+
+        for hash_function in [HashFunction::Sha1, HashFunction::Sha256] {
+            let mut ring: Vec<node::Node<u32, Vec<u8>, 32>> = (0..5)
+                .map(|port| {
+                    node::Node::from_address(&format!("127.0.0.1:{}", 4000 + port), hash_function)
+                })
+                .collect();
+            join_all(&mut ring);
+            let keys = ["apple", "banana", "cherry", "durian", "elderberry"];
+            for (value, key) in keys.iter().enumerate() {
+                ring[value]
+                    .insert_key(key, key.repeat(value + 1).into_bytes())
+                    .unwrap();
+            }
+            ring[1]
+                .insert_key(b"raw bytes".as_slice(), vec![0, 159, 146, 150])
+                .unwrap();
+            for n in ring.iter() {
+                for (value, key) in keys.iter().enumerate() {
+                    assert_eq!(
+                        n.find_key(key).unwrap(),
+                        Some(key.repeat(value + 1).into_bytes())
+                    );
+                }
+                assert_eq!(
+                    n.find_key(b"raw bytes".as_slice()).unwrap(),
+                    Some(vec![0, 159, 146, 150])
+                );
+                assert_eq!(n.find_key("missing").unwrap(), None);
+            }
+            ring[3].remove_key("banana").unwrap();
+            assert_eq!(ring[0].find_key("banana").unwrap(), None);
+            assert_healthy(&ring);
+        }
+    }
+
+    #[test]
+    fn test_storage_backends() {
+        let mut memory = MemoryStorage::<u8, u32>::new();
+        for id in [5u8, 10, 10, 200, 250] {
+            memory.put(Key::from_id(id), id as u32).unwrap();
+        }
+        memory
+            .put(Key::from_name(b"x", HashFunction::Sha1, 8), 1)
+            .unwrap();
+        let ids =
+            |entries: Vec<(Key<u8>, u32)>| entries.iter().map(|(k, _)| k.id).collect::<Vec<_>>();
+        assert_eq!(ids(memory.range(5, 200)), vec![10, 114, 200]);
+        assert_eq!(ids(memory.range(200, 5)), vec![250, 5]);
+        assert_eq!(memory.range(7, 7).len(), memory.len());
+        assert_eq!(ids(memory.drain_range(240, 10).unwrap()), vec![250, 5, 10]);
+        assert_eq!(memory.len(), 2);
+        assert_eq!(ids(memory.range(10, 10)), vec![114, 200]);
+        assert_eq!(memory.delete(&Key::from_id(200)).unwrap(), Some(200));
+        assert_eq!(memory.get(&Key::from_id(200)), None);
+
+        let path =
+            std::env::temp_dir().join(format!("chord-file-storage-{}.json", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        {
+            let mut file = FileStorage::<u8, Vec<u8>>::open(&path).unwrap();
+            file.put(Key::from_id(3), b"three".to_vec()).unwrap();
+            file.put(
+                Key::from_name(b"doc", HashFunction::Sha1, 8),
+                b"body".to_vec(),
+            )
+            .unwrap();
+            file.put(Key::from_id(9), b"nine".to_vec()).unwrap();
+            file.delete(&Key::from_id(9)).unwrap();
+        }
+        let file = FileStorage::<u8, Vec<u8>>::open(&path).unwrap();
+        assert_eq!(file.len(), 2);
+        assert_eq!(file.get(&Key::from_id(3)), Some(b"three".to_vec()));
+        assert_eq!(
+            file.get(&Key::from_name(b"doc", HashFunction::Sha1, 8)),
+            Some(b"body".to_vec())
+        );
+
+        // keys migrate through the storage trait on join and leave
+        let mut n0 =
+            node::Node::<u8, Vec<u8>, 8>::with_storage(0, HashFunction::Sha1, Box::new(file));
+        n0.join(None).unwrap();
+        let mut n1 = node::Node::<u8, Vec<u8>, 8>::new(50);
+        n1.join(Some(n0.clone())).unwrap();
+        assert_eq!(FileStorage::<u8, Vec<u8>>::open(&path).unwrap().len(), 1);
+        assert_eq!(n0.find(3).unwrap(), Some(b"three".to_vec()));
+        n1.leave().unwrap();
+        assert_healthy([&n0, &n1]);
+        assert_eq!(FileStorage::<u8, Vec<u8>>::open(&path).unwrap().len(), 2);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_durable_storage_recovery() {
+        let dir = std::env::temp_dir().join(format!("chord-durable-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        {
+            let mut storage = DurableStorage::<u8, String>::open(&dir, 4).unwrap();
+            for id in 1..=6u8 {
+                storage.put(Key::from_id(id), id.to_string()).unwrap();
+            }
+            storage.delete(&Key::from_id(2)).unwrap();
+            storage.drain_range(4, 5).unwrap();
+        }
+        // a record torn by a crash is dropped on recovery
+        let log = dir.join("wal.log");
+        let mut data = std::fs::read(&log).unwrap();
+        let intact = data.len();
+        data.extend_from_slice(b"{\"Put\":[{\"id\":9");
+        std::fs::write(&log, &data).unwrap();
+        {
+            let mut storage = DurableStorage::<u8, String>::open(&dir, 4).unwrap();
+            assert_eq!(std::fs::metadata(&log).unwrap().len() as usize, intact);
+            let ids: Vec<u8> = storage.range(0, 0).iter().map(|(k, _)| k.id).collect();
+            assert_eq!(ids, vec![1, 3, 4, 6]);
+            storage.snapshot().unwrap();
+            assert_eq!(std::fs::metadata(&log).unwrap().len(), 0);
+        }
+        assert_eq!(
+            DurableStorage::<u8, String>::open(&dir, 4).unwrap().len(),
+            4
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        // a restarted node rebuilds its keys from disk and rejoins the ring
+        let dir = std::env::temp_dir().join(format!("chord-node-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        {
+            let mut n0 = node::Node::<u8, String, 8>::new(0);
+            n0.join(None).unwrap();
+            let mut n1 = node::Node::<u8, String, 8>::open(100, HashFunction::Sha1, &dir).unwrap();
+            n1.join(Some(n0.clone())).unwrap();
+            for key in [30u8, 70, 100, 200] {
+                n0.insert(key, format!("v{}", key)).unwrap();
+            }
+        }
+        let mut n0 = node::Node::<u8, String, 8>::new(0);
+        n0.join(None).unwrap();
+        let mut n2 = node::Node::<u8, String, 8>::new(50);
+        n2.join(Some(n0.clone())).unwrap();
+        let mut n1 = node::Node::<u8, String, 8>::open(100, HashFunction::Sha1, &dir).unwrap();
+        n1.join(Some(n2.clone())).unwrap();
+        for key in [30u8, 70, 100] {
+            assert_eq!(n0.find(key).unwrap(), Some(format!("v{}", key)));
+        }
+        assert_eq!(n0.find(200).unwrap(), None);
+        assert_healthy([&n0, &n1, &n2]);
+        drop((n0, n1, n2));
+        assert_eq!(
+            DurableStorage::<u8, String>::open(&dir, 4).unwrap().len(),
+            2
+        );
+
+        // the last node of a ring keeps its keys when it leaves
+        {
+            let mut n1 = node::Node::<u8, String, 8>::open(100, HashFunction::Sha1, &dir).unwrap();
+            n1.join(None).unwrap();
+            n1.leave().unwrap();
+            assert_eq!(n1.keys().len(), 2);
+        }
+        assert_eq!(
+            DurableStorage::<u8, String>::open(&dir, 4).unwrap().len(),
+            2
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_concurrent_joins_stabilize() {
+        let ids = [
+            200u8, 10, 90, 130, 55, 240, 30, 170, 110, 75, 5, 150, 220, 45,
+        ];
+        let mut ring: Vec<node::Node<u8, u8, 8>> =
+            ids.iter().map(|id| node::Node::new(*id)).collect();
+        ring[0].join_stabilized(None).unwrap();
+        for key in (0..=255u8).step_by(7) {
+            ring[0].insert(key, key).unwrap();
+        }
+        // everyone joins through the first node before any maintenance runs
+        for i in 1..ring.len() {
+            let bootstrap = ring[0].clone();
+            ring[i].join_stabilized(Some(bootstrap)).unwrap();
+        }
+        assert_eq!(tick_all(&mut ring, 3 * 8), 0);
+
+        let mut sorted = ids.to_vec();
+        sorted.sort();
+        for (i, id) in sorted.iter().enumerate() {
+            let n = ring.iter().find(|n| n.id() == *id).unwrap();
+            assert_eq!(n.successor_id(), sorted[(i + 1) % sorted.len()]);
+            assert_eq!(
+                n.predecessor_id(),
+                Some(sorted[(i + sorted.len() - 1) % sorted.len()])
+            );
+        }
+        for n in ring.iter() {
+            for key in (0..=255u8).step_by(7) {
+                assert_eq!(n.find(key).unwrap(), Some(key));
+            }
+        }
+        assert_healthy(&ring);
+    }
+
+    #[test]
+    fn test_successor_lists() {
+        let ids = [0u8, 30, 65, 110, 160, 230];
+        for successor_list_len in [1, 3, 10] {
+            let config = Config {
+                successor_list_len,
+                ..Config::default()
+            };
+            let mut ring: Vec<node::Node<u8, u8, 8>> = ids
+                .iter()
+                .map(|id| {
+                    node::Node::with_config(*id, config.clone(), Box::new(MemoryStorage::new()))
+                })
+                .collect();
+            join_all_stabilized(&mut ring);
+            assert_eq!(tick_all(&mut ring, 2 * ids.len()), 0);
+            for (i, n) in ring.iter().enumerate() {
+                let expected: Vec<u8> = (1..ids.len())
+                    .map(|j| ids[(i + j) % ids.len()])
+                    .take(successor_list_len)
+                    .collect();
+                assert_eq!(n.successor_list(), expected);
+            }
+            // fix_fingers refreshes one finger a round; give each one a turn
+            // on the settled ring
+            assert_eq!(tick_all(&mut ring, 8), 0);
+            assert_healthy(&ring);
+        }
+    }
+
+    #[test]
+    fn test_duplicate_ids() {
+        let ids = [0u8, 100, 200];
+        let mut ring: Vec<Node> = ids.iter().map(|id| Node::new(*id)).collect();
+        join_all(&mut ring);
+        ring[1].insert(150, Some(150)).unwrap();
+
+        // a second node 100 is turned away before touching the ring
+        let duplicate = Error::DuplicateId {
+            id: "100".to_string(),
+        };
+        assert_eq!(
+            Node::new(100).join(Some(ring[0].clone())),
+            Err(duplicate.clone())
+        );
+        assert_eq!(
+            Node::new(100).join_stabilized(Some(ring[2].clone())),
+            Err(duplicate)
+        );
+        for (i, n) in ring.iter().enumerate() {
+            assert_eq!(n.successor_id(), ids[(i + 1) % ids.len()]);
+            assert_eq!(n.predecessor_id(), Some(ids[(i + 2) % ids.len()]));
+        }
+
+        // or takes the first free salted hash of its old identifier
+        let config = Config {
+            id_collision: IdCollision::Rehash,
+            ..Config::default()
+        };
+        let mut rehashed = Node::with_config(100, config, Box::new(MemoryStorage::new()));
+        let handle = rehashed.clone();
+        rehashed.join(Some(ring[0].clone())).unwrap();
+        let id: u8 = HashFunction::Sha1.hash(b"100#1", 8);
+        assert_eq!(rehashed.id(), id);
+        assert_eq!(handle.id(), id);
+        assert!(!ids.contains(&id));
+        ring.push(rehashed);
+        let mut live: Vec<u8> = ring.iter().map(|n| n.id()).collect();
+        live.sort();
+        for key in [id, 99, 100, 150, 201] {
+            let lookup = ring[2].lookup(key).unwrap();
+            assert_eq!(lookup.owner, owner(&live, key));
+        }
+        assert_eq!(ring[3].find(150).unwrap(), Some(Some(150)));
+        assert_healthy(&ring);
+    }
+
+    #[cfg(feature = "shell")]
+    #[test]
+    fn test_shell() {
+        let mut shell = Shell::new();
+        for line in [
+            "create 0",
+            "create 100",
+            "create 200",
+            "join 0",
+            "join 100 0",
+            "join 200 100",
+            "tick 3",
+            "insert 0 42 hello world",
+            "insert 200 apple red",
+        ] {
+            shell.execute(line).unwrap();
+        }
+        assert_eq!(
+            shell.execute("get 200 42").unwrap(),
+            "42: \"hello world\" at node 100 via [200, 0, 100]"
+        );
+        assert!(shell
+            .execute("get 0 apple")
+            .unwrap()
+            .starts_with("apple: \"red\""));
+        assert_eq!(
+            shell.execute("lookups 200").unwrap(),
+            "lookup of key 42 from node 200 via [200, 0, 100]: found at node 100"
+        );
+        shell.execute("delete 0 42").unwrap();
+        assert!(shell.execute("get 0 42").unwrap().contains("not found"));
+        assert!(matches!(
+            shell.execute("delete 0 42"),
+            Err(ShellError::Node(Error::KeyNotFound { .. }))
+        ));
+        assert!(matches!(
+            shell.execute("create 100"),
+            Err(ShellError::NodeExists(100))
+        ));
+        assert!(matches!(
+            shell.execute("join 7 0"),
+            Err(ShellError::NoSuchNode(7))
+        ));
+        assert!(matches!(
+            shell.execute("join 100 0"),
+            Err(ShellError::Node(Error::AlreadyJoined { .. }))
+        ));
+        assert!(matches!(
+            shell.execute("insert 0 42"),
+            Err(ShellError::Usage(_))
+        ));
+        assert!(matches!(
+            shell.execute("frobnicate"),
+            Err(ShellError::UnknownCommand(_))
+        ));
+
+        // the others route around a crashed node once maintenance has run
+        shell.execute("events").unwrap();
+        shell.execute("crash 100").unwrap();
+        assert_eq!(shell.execute("events").unwrap(), "node 100 crashed");
+        shell.execute("tick 3").unwrap();
+        assert_eq!(
+            shell.execute("ring").unwrap(),
+            "node   0 up    pred 200 succ 200 list [200] keys 0\n\
+             node 100 down  pred   0 succ 200 list [200, 0] keys 1\n\
+             node 200 up    pred   0 succ   0 list [0] keys 0"
+        );
+        shell.execute("join 100 0").unwrap();
+        shell.execute("leave 200").unwrap();
+        shell.execute("join 200 100").unwrap();
+        assert!(shell
+            .execute("get 0 apple")
+            .unwrap()
+            .contains("at node 100"));
+        assert_eq!(
+            shell.execute("check").unwrap(),
+            "3 live nodes, 0 violations"
+        );
+        assert!(!shell.finished());
+        shell.execute("quit").unwrap();
+        assert!(shell.finished());
+    }
+
+    #[cfg(feature = "shell")]
+    #[test]
+    fn test_scenarios() {
+        let sample = include_str!("../scenarios/sample_case.txt");
+        let report = scenario::run(&mut Shell::new(), sample);
+        assert!(report.passed(), "{}", report);
+        assert_eq!((report.commands, report.checks), (27, 19));
+
+        let report = scenario::run(
+            &mut Shell::new(),
+            "create 0\n\
+             join 0\n\
+             # a comment\n\
+             insert 0 5 five\n\
+             expect keys 0 5 6\n\
+             expect value 0 5 six\n\
+             expect error get 0 5\n\
+             leave 1\n",
+        );
+        let problems: Vec<(usize, &str)> = report
+            .mismatches
+            .iter()
+            .map(|Mismatch { line, problem, .. }| (*line, problem.as_str()))
+            .collect();
+        assert_eq!(
+            problems,
+            [
+                (5, "node holds [5], expected [5, 6]"),
+                (6, "found \"five\", expected \"six\""),
+                (7, "the command succeeded, expected an error"),
+                (8, "no node 1"),
+            ]
+        );
+    }
+
+    #[cfg(feature = "shell")]
+    #[test]
+    fn test_export() {
+        let mut shell = Shell::new();
+        let sample = include_str!("../scenarios/sample_case.txt");
+        assert!(scenario::run(&mut shell, sample).passed());
+        assert_eq!(
+            shell.execute("export dot").unwrap(),
+            include_str!("../scenarios/sample_case.dot")
+        );
+        let json = shell.execute("export json").unwrap();
+        assert_eq!(json, include_str!("../scenarios/sample_case.json"));
+        assert!(matches!(
+            shell.execute("export svg"),
+            Err(ShellError::Usage(_))
+        ));
+
+        // the snapshot reads back, fingers and all
+        let snapshot: RingSnapshot<u8> = serde_json::from_str(&json).unwrap();
+        assert_eq!(snapshot.nodes.len(), 6);
+        let n0 = &snapshot.nodes[0];
+        assert_eq!((n0.id, n0.predecessor, n0.successor), (0, Some(230), 30));
+        let last = &n0.fingers[7];
+        assert_eq!((last.start, last.end, last.node), (128, 0, Some(160)));
+        assert_eq!(snapshot.nodes.iter().map(|n| n.keys).sum::<usize>(), 12);
+    }
+
+    #[test]
+    fn test_ring_handle() {
+        let mut ring = Ring::<u8, Option<u8>, 8>::new();
+        assert_eq!(ring.find(3), Err(Error::EmptyRing));
+        for id in [0u8, 30, 65, 110, 160, 230] {
+            ring.add(id).unwrap();
+        }
+        assert_eq!(
+            ring.add(65).map(|n| n.id()),
+            Err(Error::DuplicateId {
+                id: "65".to_string()
+            })
+        );
+        let keys = [3u8, 200, 123, 45, 99, 60, 50, 100, 101, 102, 240, 250];
+        for key in keys {
+            ring.insert(key, Some(key)).unwrap();
+        }
+        let live = [0u8, 30, 65, 110, 160, 230];
+        for n in ring.nodes() {
+            let held: Vec<u8> = n.keys().iter().map(|(k, _)| k.id).collect();
+            assert!(held.iter().all(|k| owner(&live, *k) == n.id()));
+        }
+        assert_eq!(ring.lookup(100).unwrap().owner, 110);
+
+        // nodes joined by hand show up in the ring like the others, once
+        // they have joined
+        let created = ring.create(100).unwrap();
+        assert!(!created.is_alive());
+        assert_eq!(ring.live_nodes().count(), 6);
+        ring.insert(90, Some(90)).unwrap();
+        assert_eq!(ring.lookup(90).unwrap().owner, 110);
+        assert!(ring.audit().is_healthy());
+        assert_eq!(ring.join(100, Some(230)), Ok(100));
+        assert_eq!(ring.node(110).unwrap().predecessor_id(), Some(100));
+        assert_eq!(created.keys()[0], (Key::from_id(90), Some(90)));
+        assert_eq!(
+            ring.join(7, None),
+            Err(Error::UnknownNode {
+                node: "7".to_string()
+            })
+        );
+
+        // successor lists fill in over maintenance rounds, before the crash
+        ring.tick(3);
+        ring.leave(110).unwrap();
+        ring.crash(0).unwrap();
+        ring.tick(8);
+        let ids: Vec<u8> = ring.live_nodes().map(|n| n.id()).collect();
+        assert_eq!(ids, [30, 65, 100, 160, 230]);
+        assert_eq!(ring.len(), 7);
+        for key in [3u8, 100, 101, 123] {
+            assert_eq!(ring.find(key).unwrap(), Some(Some(key)));
+        }
+        ring.remove(101).unwrap();
+        assert_eq!(ring.find(101).unwrap(), None);
+        let report = ring.audit();
+        assert!(report.is_healthy(), "{}", report);
+        assert_eq!(report.nodes, 5);
+
+        // a new node takes the place of one that crashed
+        let fresh = ring.add(0).unwrap();
+        assert!(fresh.is_alive());
+        assert_eq!(ring.len(), 7);
+        assert_eq!(ring.live_nodes().count(), 6);
+        assert!(ring.create(110).is_ok());
+        assert_eq!(
+            ring.create(65).map(|n| n.id()),
+            Err(Error::DuplicateId {
+                id: "65".to_string()
+            })
+        );
+
+        // or a new identifier if the configuration asks for one
+        let mut ring = Ring::<u8, Option<u8>, 8>::with_config(Config {
+            id_collision: IdCollision::Rehash,
+            ..Config::default()
+        });
+        ring.add(100).unwrap();
+        let rehashed = ring.add(100).unwrap().id();
+        assert_eq!(rehashed, HashFunction::Sha1.hash::<u8>(b"100#1", 8));
+        let ids: Vec<u8> = ring.live_nodes().map(|n| n.id()).collect();
+        assert_eq!(ids.len(), 2);
+        assert!(ids.contains(&100) && ids.contains(&rehashed));
+    }
+
+    fn next_random(seed: &mut u64) -> u64 {
+        // 64-bit LCG constants from Knuth's MMIX
+        *seed = seed
+            .wrapping_mul(6364136223846793005)
+            .wrapping_add(1442695040888963407);
+        *seed >> 33
+    }
+
+    /// Joins `nodes` into one ring, each through the node before it.
+    fn join_all<I, V, const M: u32>(nodes: &mut [node::Node<I, V, M>])
+    where
+        I: Identifier,
+        V: Clone + Send + 'static,
+    {
+        nodes[0].join(None).unwrap();
+        for i in 1..nodes.len() {
+            let bootstrap = nodes[i - 1].clone();
+            nodes[i].join(Some(bootstrap)).unwrap();
+        }
+    }
+
+    /// Like [`join_all`], leaving the rest to maintenance.
+    fn join_all_stabilized<I, V, const M: u32>(nodes: &mut [node::Node<I, V, M>])
+    where
+        I: Identifier,
+        V: Clone + Send + 'static,
+    {
+        nodes[0].join_stabilized(None).unwrap();
+        for i in 1..nodes.len() {
+            let bootstrap = nodes[i - 1].clone();
+            nodes[i].join_stabilized(Some(bootstrap)).unwrap();
+        }
+    }
+
+    /// Runs `rounds` rounds of maintenance on every node and returns how
+    /// many of them failed, like [`Ring::tick`].
+    fn tick_all<I, V, const M: u32>(nodes: &mut [node::Node<I, V, M>], rounds: usize) -> usize
+    where
+        I: Identifier,
+        V: Clone + Send + 'static,
+    {
+        let mut failures = 0;
+        for _ in 0..rounds {
+            for n in nodes.iter_mut() {
+                if n.tick().is_err() {
+                    failures += 1;
+                }
+            }
+        }
+        failures
+    }
+
+    /// The `test_sample_case` topology with its keys, kept up by maintenance ticks.
+    fn sample_ring() -> Vec<Node> {
+        let ids = [0u8, 30, 65, 110, 160, 230];
+        let mut ring: Vec<Node> = ids.iter().map(|id| Node::new(*id)).collect();
+        join_all(&mut ring);
+        for (i, key) in [3u8, 200, 123, 45, 99, 60, 50, 100, 101, 102, 240, 250]
+            .iter()
+            .enumerate()
+        {
+            ring[i % ids.len()].insert(*key, Some(*key)).unwrap();
+        }
+        // successor lists fill in one hop per round
+        assert_eq!(tick_all(&mut ring, 2 * ids.len()), 0);
+        ring
+    }
+
+    fn owner(live: &[u8], id: u8) -> u8 {
+        *live.iter().find(|n| **n >= id).unwrap_or(&live[0])
+    }
+
+    /// Fails unless the live nodes among `nodes` form a consistent ring.
+    fn assert_healthy<'a, I, V, const M: u32>(
+        nodes: impl IntoIterator<Item = &'a node::Node<I, V, M>>,
+    ) where
+        I: Identifier,
+        V: Clone + Send + 'static,
+    {
+        let report = audit(nodes);
+        assert!(report.is_healthy(), "{}", report);
+    }
+
+    #[test]
+    fn test_audit() {
+        let mut ring: Ring<u8, u8, 8> = Ring::new();
+        for id in [0, 100, 200] {
+            ring.add(id).unwrap();
+        }
+        ring.tick(3);
+        assert_eq!(ring.audit().to_string(), "3 live nodes, 0 violations");
+
+        // a crash goes unnoticed until maintenance catches up with it
+        ring.crash(100).unwrap();
+        let report = ring.audit();
+        assert_eq!(report.nodes, 2);
+        assert!(report.violations.contains(&Violation::DeadSuccessor {
+            node: 0,
+            successor: 100
+        }));
+        assert!(report.violations.contains(&Violation::WrongFinger {
+            node: 200,
+            index: 8,
+            finger: Some(100),
+            expected: 200
+        }));
+        ring.tick(16);
+        assert!(ring.audit().is_healthy(), "{}", ring.audit());
+    }
+
+    #[test]
+    fn test_crash_random_nodes() {
+        let keys = [3u8, 200, 123, 45, 99, 60, 50, 100, 101, 102, 240, 250];
+        for seed in 1..=20u64 {
+            let mut seed = seed;
+            let mut ring = sample_ring();
+            let all: Vec<u8> = ring.iter().map(|n| n.id()).collect();
+            let crashes = 1 + next_random(&mut seed) as usize % 3;
+            for _ in 0..crashes {
+                let live: Vec<usize> = (0..ring.len()).filter(|i| ring[*i].is_alive()).collect();
+                let victim = live[next_random(&mut seed) as usize % live.len()];
+                ring[victim].crash();
+            }
+            let live: Vec<u8> = ring
+                .iter()
+                .filter(|n| n.is_alive())
+                .map(|n| n.id())
+                .collect();
+            let surviving_keys: Vec<u8> = keys
+                .iter()
+                .copied()
+                .filter(|k| live.contains(&owner(&all, *k)))
+                .collect();
+            // successor lists keep lookups working before any repair
+            for key in surviving_keys.iter() {
+                for n in ring.iter().filter(|n| n.is_alive()) {
+                    assert_eq!(
+                        n.find(*key).unwrap(),
+                        Some(Some(*key)),
+                        "seed {} key {}",
+                        seed,
+                        key
+                    );
+                }
+            }
+
+            tick_all(&mut ring, 2 * 8);
+            for (i, n) in ring.iter().filter(|n| n.is_alive()).enumerate() {
+                assert_eq!(n.successor_id(), live[(i + 1) % live.len()]);
+                assert_eq!(
+                    n.predecessor_id(),
+                    Some(live[(i + live.len() - 1) % live.len()])
+                );
+                for (k, finger) in n.finger_ids().into_iter().enumerate() {
+                    let start =
+                        node::FingerTable::<u8, Option<u8>, 8>::finger_id(n.id(), k as u32 + 1);
+                    assert_eq!(finger, Some(owner(&live, start)));
+                }
+            }
+            assert_healthy(&ring);
+            for key in surviving_keys.iter() {
+                for n in ring.iter().filter(|n| n.is_alive()) {
+                    assert_eq!(
+                        n.find(*key).unwrap(),
+                        Some(Some(*key)),
+                        "seed {} key {}",
+                        seed,
+                        key
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_rejoin() {
+        let keys = [3u8, 200, 123, 45, 99, 60, 50, 100, 101, 102, 240, 250];
+        let mut ring = sample_ring();
+        // a node has to leave before joining again
+        let already_joined = Err(Error::AlreadyJoined {
+            node: "110".to_string(),
+        });
+        let bootstrap = ring[0].clone();
+        assert_eq!(ring[3].join(Some(bootstrap.clone())), already_joined);
+        assert_eq!(ring[3].join_stabilized(Some(bootstrap)), already_joined);
+        assert_eq!(ring[3].join(None), already_joined);
+        assert_healthy(&ring);
+
+        ring[3].leave().unwrap();
+        let bootstrap = ring[0].clone();
+        ring[3].join(Some(bootstrap)).unwrap();
+        assert!(ring[3].is_alive());
+        assert_eq!(ring[3].predecessor_id(), Some(65));
+        assert_healthy(&ring);
+
+        // a crashed node comes back with the keys it held
+        ring[4].crash();
+        assert_eq!(ring[0].find(123).unwrap(), None);
+        tick_all(&mut ring, 2 * 8);
+        let bootstrap = ring[0].clone();
+        ring[4].join(Some(bootstrap)).unwrap();
+        assert_healthy(&ring);
+        for n in ring.iter() {
+            for key in keys.iter() {
+                assert_eq!(n.find(*key).unwrap(), Some(Some(*key)));
+            }
+        }
+    }
+
+    #[test]
+    fn test_stale_replicas() {
+        let config = Config {
+            replication_factor: 2,
+            ..Config::default()
+        };
+        let mut ring: Ring<u8, u8, 8> = Ring::with_config(config);
+        for id in [0, 100, 200] {
+            ring.add(id).unwrap();
+        }
+        ring.tick(3);
+        ring.insert(50, 7).unwrap();
+        // node 200 no longer holds the replica of key 50 once 150 joins, so a
+        // copy it kept would outlive the key
+        ring.add(150).unwrap();
+        ring.tick(3);
+        ring.remove(50).unwrap();
+        ring.crash(100).unwrap();
+        ring.crash(150).unwrap();
+        ring.tick(3);
+        assert_eq!(ring.find(50).unwrap(), None);
+        // fix_fingers refreshes one finger a round
+        ring.tick(8);
+        assert_healthy(ring.nodes());
+    }
+
+    #[test]
+    fn test_replication_survives_crashes() {
+        let config = Config {
+            replication_factor: 3,
+            ..Config::default()
+        };
+        let ids = [0u8, 30, 65, 110, 160, 230];
+        let keys = [3u8, 200, 123, 45, 99, 60, 50, 100, 101, 102, 240, 250];
+        let mut ring: Vec<Node> = ids
+            .iter()
+            .map(|id| Node::with_config(*id, config.clone(), Box::new(MemoryStorage::new())))
+            .collect();
+        join_all_stabilized(&mut ring);
+        tick_all(&mut ring, 2 * ids.len());
+        for (i, key) in keys.iter().enumerate() {
+            ring[i % ids.len()].insert(*key, Some(*key)).unwrap();
+        }
+        ring[1].remove(123).unwrap();
+
+        // two adjacent owners fail at once; their keys live on as replicas
+        ring[2].crash();
+        ring[3].crash();
+        for key in keys.iter().filter(|k| **k != 123) {
+            assert_eq!(ring[0].find(*key).unwrap(), Some(Some(*key)));
+        }
+        tick_all(&mut ring, 2 * ids.len());
+
+        // replicas were repaired, so two more failures still lose nothing
+        ring[4].crash();
+        ring[5].crash();
+        tick_all(&mut ring, 2 * ids.len());
+        for n in ring.iter().filter(|n| n.is_alive()) {
+            for key in keys.iter() {
+                let expected = if *key == 123 { None } else { Some(Some(*key)) };
+                assert_eq!(n.find(*key).unwrap(), expected);
+            }
+        }
+        assert_healthy(&ring);
+    }
+
+    /// Runs a node on its own thread, standing in for a separate process,
+    /// until `stop` is set.
+    fn spawn_tcp_node(id: u8, stop: Arc<AtomicBool>) -> (RemoteNode, thread::JoinHandle<()>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        let node_address = address.clone();
+        let handle = thread::spawn(move || {
+            let node = Node::with_transport(
+                id,
+                node_address,
+                Config::default(),
+                Box::new(MemoryStorage::new()),
+                Arc::new(TcpTransport::default()),
+            );
+            serve(&node, &listener, &stop, None, |_| {}).unwrap();
+        });
+        let remote = RemoteNode::connect(&address, Arc::new(TcpTransport::default())).unwrap();
+        (remote, handle)
+    }
+
+    /// Runs the `test_sample_case` operations against nodes with the sample
+    /// identifiers followed by 100, sending each operation to its node.
+    fn check_sample_scenario(nodes: &[RemoteNode]) {
+        let ids: Vec<u8> = nodes.iter().map(|n| n.id()).collect();
+        assert_eq!(ids, [0u8, 30, 65, 110, 160, 230, 100]);
+        for n in nodes.iter() {
+            assert!(!n.ping());
+        }
+
+        nodes[0].join(None).unwrap();
+        for i in 1..6 {
+            nodes[i].join(Some(&nodes[i - 1])).unwrap();
+        }
+        for n in nodes[..6].iter() {
+            assert!(n.ping());
+        }
+        let inserts = [
+            (0, 3u8, Some(3u8)),
+            (1, 200, None),
+            (2, 123, None),
+            (3, 45, Some(3)),
+            (4, 99, None),
+            (2, 60, Some(10)),
+            (0, 50, Some(8)),
+            (3, 100, Some(5)),
+            (3, 101, Some(4)),
+            (3, 102, Some(6)),
+            (5, 240, Some(8)),
+            (5, 250, Some(10)),
+        ];
+        for (i, key, value) in inserts {
+            nodes[i].insert(key, value).unwrap();
+        }
+        nodes[6].join(Some(&nodes[5])).unwrap();
+
+        let check = |live: &[usize]| {
+            let mut live_ids: Vec<u8> = live.iter().map(|i| ids[*i]).collect();
+            live_ids.sort();
+            for i in live.iter() {
+                let mut held: Vec<u8> = nodes[*i]
+                    .keys()
+                    .unwrap()
+                    .iter()
+                    .map(|(k, _)| k.id)
+                    .collect();
+                held.sort();
+                let mut expected: Vec<u8> = inserts
+                    .iter()
+                    .map(|(_, key, _)| *key)
+                    .filter(|key| owner(&live_ids, *key) == ids[*i])
+                    .collect();
+                expected.sort();
+                assert_eq!(held, expected, "keys of node {}", ids[*i]);
+            }
+            for i in [0, 6] {
+                for (_, key, value) in inserts {
+                    assert_eq!(nodes[i].find(key).unwrap(), Some(value));
+                }
+            }
+        };
+        check(&[0, 1, 2, 3, 4, 5, 6]);
+
+        nodes[2].leave().unwrap();
+        check(&[0, 1, 3, 4, 5, 6]);
+    }
+
+    #[test]
+    fn test_tcp_cluster() {
+        let stop = Arc::new(AtomicBool::new(false));
+        let ids = [0u8, 30, 65, 110, 160, 230, 100];
+        let (nodes, handles): (Vec<RemoteNode>, Vec<_>) = ids
+            .iter()
+            .map(|id| spawn_tcp_node(*id, Arc::clone(&stop)))
+            .unzip();
+        check_sample_scenario(&nodes);
+
+        stop.store(true, Ordering::Relaxed);
+        for handle in handles {
+            handle.join().unwrap();
+        }
+    }
+
+    /// Nodes with `ids` that reach each other only through a shared
+    /// in-memory transport, with client handles to them.
+    fn memory_ring(ids: &[u8], config: &Config) -> (Vec<Node>, Vec<RemoteNode>) {
+        let transport = Arc::new(MemoryTransport::new());
+        ids.iter()
+            .map(|id| {
+                let address = format!("node-{}", id);
+                let node = Node::with_transport(
+                    *id,
+                    address.clone(),
+                    config.clone(),
+                    Box::new(MemoryStorage::new()),
+                    transport.clone(),
+                );
+                transport.register(&node).unwrap();
+                let remote = RemoteNode::connect(&address, transport.clone()).unwrap();
+                (node, remote)
+            })
+            .unzip()
+    }
+
+    #[test]
+    fn test_memory_transport() {
+        let (ring, nodes) = memory_ring(&[0, 30, 65, 110, 160, 230, 100], &Config::default());
+        check_sample_scenario(&nodes);
+        assert_healthy(&ring);
+    }
+
+    #[test]
+    fn test_metrics() {
+        let mut ring: Ring<u8, u8, 8> = Ring::new();
+        for id in [0, 100, 200] {
+            ring.add(id).unwrap();
+        }
+        for key in [50, 150, 250] {
+            ring.insert(key, key).unwrap();
+        }
+        let n0 = ring.node(0).unwrap();
+        let hops = n0.metrics().hops;
+        let lookup = n0.lookup(150).unwrap();
+        let metrics = n0.metrics();
+        assert_eq!(metrics.lookups_initiated, 1);
+        assert_eq!(metrics.keys_held, 1);
+        assert_eq!(metrics.hops.count, hops.count + 1);
+        assert_eq!(metrics.hops.sum, hops.sum + lookup.hops() as u64);
+        assert_eq!(metrics.hops.buckets.len(), HOP_BUCKETS.len());
+        assert_eq!(metrics.hops.buckets.last().unwrap().1, metrics.hops.count);
+        assert!(metrics.finger_updates >= 8);
+        assert_eq!(ring.node(200).unwrap().metrics().lookups_served, 1);
+
+        // node 150 takes key 150 over from node 200, then hands it back
+        ring.add(150).unwrap();
+        let n150 = ring.node(150).unwrap();
+        assert_eq!(n150.metrics().keys_migrated_in, 1);
+        assert_eq!(ring.node(200).unwrap().metrics().keys_migrated_out, 1);
+        ring.leave(150).unwrap();
+        assert_eq!(n150.metrics().keys_migrated_out, 1);
+        assert_eq!(ring.node(200).unwrap().metrics().keys_held, 1);
+
+        let text = ring.to_prometheus();
+        assert!(text.contains("# TYPE chord_lookups_initiated_total counter\n"));
+        assert!(text.contains("chord_lookups_initiated_total{node=\"0\"} 1\n"));
+        assert!(text.contains("chord_keys_held{node=\"200\"} 1\n"));
+        assert!(!text.contains("node=\"150\""));
+        let count = n0.metrics().hops.count;
+        assert!(text.contains(&format!(
+            "chord_lookup_hops_bucket{{node=\"0\",le=\"+Inf\"}} {}\n",
+            count
+        )));
+
+        // nodes reached through a transport count the requests
+        let (remote_ring, nodes) = memory_ring(&[0, 100], &Config::default());
+        nodes[0].join(None).unwrap();
+        nodes[1].join(Some(&nodes[0])).unwrap();
+        let joined = remote_ring[1].metrics();
+        assert!(joined.rpcs_sent > 0);
+        assert!(joined.rpcs_received > 0);
+        assert!(remote_ring[0].metrics().rpcs_received >= joined.rpcs_sent);
+
+        // and scrapes are answered over HTTP
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let stop = Arc::new(AtomicBool::new(false));
+        let server = {
+            let stop = Arc::clone(&stop);
+            let nodes: Vec<node::Node<u8, u8, 8>> = ring.nodes().cloned().collect();
+            thread::spawn(move || {
+                serve_metrics(&listener, &stop, || metrics::to_prometheus(&nodes)).unwrap()
+            })
+        };
+        let get = |path: &str| {
+            let mut stream = TcpStream::connect(address).unwrap();
+            write!(stream, "GET {} HTTP/1.1\r\nHost: localhost\r\n\r\n", path).unwrap();
+            let mut response = String::new();
+            stream.read_to_string(&mut response).unwrap();
+            response
+        };
+        let response = get("/metrics");
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(response.ends_with(&text));
+        assert!(get("/").starts_with("HTTP/1.1 404"));
+        stop.store(true, Ordering::Relaxed);
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn test_memory_transport_crashes() {
+        let config = Config {
+            replication_factor: 3,
+            ..Config::default()
+        };
+        let ids = [0u8, 30, 65, 110, 160, 230];
+        let keys = [3u8, 200, 123, 45, 99, 60, 50, 100, 101, 102, 240, 250];
+        let (mut ring, nodes) = memory_ring(&ids, &config);
+        nodes[0].join_stabilized(None).unwrap();
+        for i in 1..nodes.len() {
+            nodes[i].join_stabilized(Some(&nodes[i - 1])).unwrap();
+        }
+        tick_all(&mut ring, 3 * ids.len());
+        for n in ring.iter() {
+            let expected: Vec<u8> = (1..=4)
+                .map(|k| ids[(ids.iter().position(|id| *id == n.id()).unwrap() + k) % ids.len()])
+                .collect();
+            assert_eq!(n.successor_list(), expected);
+        }
+        for (i, key) in keys.iter().enumerate() {
+            nodes[i % ids.len()].insert(*key, Some(*key)).unwrap();
+        }
+
+        // the crashed nodes stop answering; their keys live on as replicas,
+        // found even before the ring notices the crash
+        ring[3].crash();
+        for n in nodes.iter().filter(|n| n.id() != 110) {
+            for key in [99u8, 100, 101, 102] {
+                assert_eq!(n.find(key).unwrap(), Some(Some(key)));
+            }
+        }
+        ring[2].crash();
+        tick_all(&mut ring, 3 * ids.len());
+        let live = [0u8, 30, 160, 230];
+        for (i, n) in nodes
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| ring[*i].is_alive())
+        {
+            let mut held: Vec<u8> = n.keys().unwrap().iter().map(|(k, _)| k.id).collect();
+            held.sort();
+            let mut expected: Vec<u8> = keys
+                .iter()
+                .copied()
+                .filter(|key| owner(&live, *key) == ids[i])
+                .collect();
+            expected.sort();
+            assert_eq!(held, expected, "keys of node {}", ids[i]);
+            for key in keys.iter() {
+                assert_eq!(n.find(*key).unwrap(), Some(Some(*key)));
+            }
+        }
+        assert_healthy(&ring);
+    }
+
+    #[test]
+    fn test_typed_errors() {
+        let not_joined = |id: &str| Error::NotJoined {
+            node: id.to_string(),
+        };
+
+        // a node outside any ring refuses requests instead of panicking
+        let mut lonely = Node::new(5);
+        assert_eq!(lonely.find(3), Err(not_joined("5")));
+        assert_eq!(lonely.insert(3, Some(3)), Err(not_joined("5")));
+        assert_eq!(
+            lonely.info(),
+            Err(Error::NoAddress {
+                node: "5".to_string()
+            })
+        );
+
+        // identifiers and keys must fit in the ring
+        let out_of_range = |id: &str| Error::IdOutOfRange {
+            id: id.to_string(),
+            bits: 8,
+        };
+        let mut wide = node::Node::<u16, u8, 8>::new(300);
+        assert_eq!(wide.join(None), Err(out_of_range("300")));
+        assert!(!wide.is_alive());
+        let mut narrow = node::Node::<u16, u8, 8>::new(30);
+        narrow.join(None).unwrap();
+        assert_eq!(narrow.insert(1000, 1), Err(out_of_range("1000")));
+        assert_eq!(narrow.find(256), Err(out_of_range("256")));
+        assert_eq!(narrow.remove(256), Err(out_of_range("256")));
+        narrow.insert(255, 1).unwrap();
+        assert_eq!(narrow.find(255), Ok(Some(1)));
+
+        // errors raised by a remote node reach the caller unchanged
+        let (mut ring, nodes) = memory_ring(&[0, 30, 65], &Config::default());
+        nodes[0].join(None).unwrap();
+        nodes[1].join(Some(&nodes[0])).unwrap();
+        nodes[2].join(Some(&nodes[1])).unwrap();
+        nodes[1].insert(42, Some(42)).unwrap();
+        assert_eq!(
+            nodes[2].remove(7),
+            Err(Error::KeyNotFound {
+                key: "7".to_string()
+            })
+        );
+        nodes[2].remove(42).unwrap();
+        assert_eq!(nodes[0].find(42), Ok(None));
+
+        // a finger index out of range is refused rather than panicking
+        for index in [0, 9] {
+            let request = Request::UpdateFingerTable {
+                node: ring[0].info().unwrap(),
+                index,
+            };
+            let response = ring[1].handle(request);
+            assert!(
+                matches!(response, Response::Error(Error::Protocol(_))),
+                "{:?}",
+                response
+            );
+        }
+
+        ring[2].leave().unwrap();
+        assert_eq!(ring[2].leave(), Err(not_joined("65")));
+        assert_eq!(ring[2].find(42), Err(not_joined("65")));
+        assert_eq!(
+            nodes[2].find(42),
+            Err(Error::NodeUnreachable {
+                address: "node-65".to_string(),
+                reason: "no response".to_string(),
+            })
+        );
+        assert_eq!(nodes[0].find(42), Ok(None));
+        assert_healthy(&ring);
+    }
+
+    #[test]
+    fn test_recursive_lookups() {
+        let ids = [0u8, 30, 65, 110, 160, 230, 100];
+        let recursive = Config {
+            lookup_mode: LookupMode::Recursive,
+            ..Config::default()
+        };
+        let (ring, nodes) = memory_ring(&ids, &recursive);
+        check_sample_scenario(&nodes);
+        assert_healthy(&ring);
+
+        // both modes visit the same nodes, and route around a failed hop
+        // before the ring repairs itself
+        for mode in [LookupMode::Iterative, LookupMode::Recursive] {
+            let config = Config {
+                lookup_mode: mode,
+                ..Config::default()
+            };
+            let (mut ring, nodes) = memory_ring(&ids, &config);
+            nodes[0].join(None).unwrap();
+            for n in nodes.iter().skip(1) {
+                n.join(Some(&nodes[0])).unwrap();
+            }
+            assert_eq!(tick_all(&mut ring, 3), 0);
+            ring[0].insert(150, Some(150)).unwrap();
+            let lookup = ring[0].lookup(150).unwrap();
+            assert_eq!(lookup.path, vec![0, 110, 160]);
+            assert_eq!(lookup.value, Some(Some(150)));
+
+            ring[3].crash();
+            let lookup = ring[0].lookup(150).unwrap();
+            assert_eq!(lookup.path, vec![0, 100, 160]);
+            assert_eq!(lookup.value, Some(Some(150)));
+            tick_all(&mut ring, 2 * 8);
+            assert_healthy(&ring);
+        }
+    }
+
+    #[test]
+    fn test_concurrent_lookups_and_inserts() {
+        let ring = sample_ring();
+        let stop = AtomicBool::new(false);
+        thread::scope(|scope| {
+            // maintenance keeps running underneath the clients
+            for _ in 0..2 {
+                scope.spawn(|| {
+                    while !stop.load(Ordering::Relaxed) {
+                        for n in ring.iter() {
+                            n.clone().tick().unwrap();
+                        }
+                    }
+                });
+            }
+            let clients: Vec<_> = (0..16u64)
+                .map(|t| {
+                    let ring = &ring;
+                    scope.spawn(move || {
+                        let mut seed = t + 1;
+                        for j in 0..16u8 {
+                            let key = t as u8 * 16 + j;
+                            let mut n = ring[next_random(&mut seed) as usize % ring.len()].clone();
+                            n.insert(key, Some(key)).unwrap();
+                            let n = &ring[next_random(&mut seed) as usize % ring.len()];
+                            assert_eq!(n.find(key).unwrap(), Some(Some(key)));
+                        }
+                    })
+                })
+                .collect();
+            for client in clients {
+                client.join().unwrap();
+            }
+            stop.store(true, Ordering::Relaxed);
+        });
+
+        let ids: Vec<u8> = ring.iter().map(|n| n.id()).collect();
+        for n in ring.iter() {
+            for (k, _) in n.keys() {
+                assert_eq!(owner(&ids, k.id), n.id());
+            }
+        }
+        let held: usize = ring.iter().map(|n| n.keys().len()).sum();
+        assert_eq!(held, 256);
+        assert_healthy(&ring);
+    }
+
+    #[test]
+    fn test_concurrent_joins() {
+        let first = Node::new(0);
+        first.clone().join_stabilized(None).unwrap();
+        let ring = Mutex::new(vec![first.clone()]);
+        let inserted = Mutex::new(Vec::new());
+        thread::scope(|scope| {
+            for t in 0..8u8 {
+                let (ring, inserted, first) = (&ring, &inserted, &first);
+                scope.spawn(move || {
+                    let mut node = Node::new(t * 31 + 7);
+                    node.join_stabilized(Some(first.clone())).unwrap();
+                    ring.lock().unwrap().push(node.clone());
+                    for round in 0..20u8 {
+                        let nodes = ring.lock().unwrap().clone();
+                        for n in nodes.iter() {
+                            // lookups may fail until the ring has settled
+                            let _ = n.clone().tick();
+                        }
+                        let key = t.wrapping_mul(97).wrapping_add(round.wrapping_mul(13));
+                        if node.insert(key, Some(key)).is_ok() {
+                            inserted.lock().unwrap().push(key);
+                        }
+                    }
+                });
+            }
+        });
+
+        let mut ring = ring.into_inner().unwrap();
+        ring.sort_by_key(|n| n.id());
+        let rounds = 2 * ring.len();
+        assert_eq!(tick_all(&mut ring, rounds), 0);
+        let ids: Vec<u8> = ring.iter().map(|n| n.id()).collect();
+        for (i, n) in ring.iter().enumerate() {
+            assert_eq!(n.successor_id(), ids[(i + 1) % ids.len()]);
+            assert_eq!(
+                n.predecessor_id(),
+                Some(ids[(i + ids.len() - 1) % ids.len()])
+            );
+        }
+        for key in inserted.into_inner().unwrap() {
+            for n in ring.iter() {
+                assert_eq!(n.find(key).unwrap(), Some(Some(key)), "key {}", key);
+            }
+        }
+        assert_healthy(&ring);
+    }
+
+    #[cfg(feature = "async")]
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_async_nodes() {
+        let config = Config {
+            stabilize_interval: Duration::from_millis(5),
+            fix_fingers_interval: Duration::from_millis(1),
+            ..Config::default()
+        };
+        let ids = [0u8, 30, 65, 110, 160, 230];
+        let mut ring: Vec<AsyncNode<u8, Option<u8>, 8>> = Vec::new();
+        for id in ids {
+            let node = AsyncNode::new(Node::with_config(
+                id,
+                config.clone(),
+                Box::new(MemoryStorage::new()),
+            ));
+            let bootstrap = ring.first().map(|n| n.node());
+            node.join(bootstrap).await.unwrap();
+            ring.push(node);
+        }
+
+        // the background tasks integrate the nodes into the ring
+        let settled = |ring: &[AsyncNode<u8, Option<u8>, 8>]| {
+            ring.iter().enumerate().all(|(i, n)| {
+                n.node().successor_id() == ids[(i + 1) % ids.len()]
+                    && n.node().predecessor_id() == Some(ids[(i + ids.len() - 1) % ids.len()])
+            })
+        };
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while !settled(&ring) {
+            assert!(Instant::now() < deadline, "ring did not stabilize");
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+
+        for key in (0..=255u8).step_by(5) {
+            ring[key as usize % ring.len()]
+                .insert(key, Some(key))
+                .await
+                .unwrap();
+        }
+        ring[3].remove(110).await.unwrap();
+        for n in ring.iter() {
+            assert_eq!(n.find(110).await.unwrap(), None);
+            assert_eq!(n.find(35).await.unwrap(), Some(Some(35)));
+        }
+
+        // shutting down hands the node's keys to its successor
+        let n2 = ring.remove(2);
+        assert!(n2.node().keys().iter().any(|(k, _)| k.id == 65));
+        n2.shutdown().await.unwrap();
+        assert!(ring[2].node().keys().iter().any(|(k, _)| k.id == 65));
+        for n in ring.iter() {
+            for key in (0..=255u8).step_by(5).filter(|k| *k != 110) {
+                assert_eq!(n.find(key).await.unwrap(), Some(Some(key)), "key {}", key);
+            }
+        }
+        // as do they with the fingers that pointed to it
+        let deadline = Instant::now() + Duration::from_secs(10);
+        loop {
+            let nodes: Vec<Node> = ring.iter().map(|n| n.node()).collect();
+            let report = audit(&nodes);
+            if report.is_healthy() {
+                break;
+            }
+            assert!(Instant::now() < deadline, "{}", report);
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        for n in ring {
+            n.shutdown().await.unwrap();
+        }
+    }
+
+    #[test]
+    fn test_simulation_without_faults() {
+        let config = SimConfig {
+            nodes: 200,
+            keys: 500,
+            duration: Duration::from_secs(20),
+            lookups_per_sec: 20.0,
+            ..SimConfig::default()
+        };
+        let report = Simulation::<32>::new(config).unwrap().run().unwrap();
+        println!("{}", report);
+        assert!(report.lookups > 300);
+        assert_eq!(report.failed_lookups, 0);
+        assert_eq!(report.lost_messages, 0);
+        assert_eq!(report.lost_keys, 0);
+        assert_eq!(report.live_nodes, 200);
+        // O(log n) hops: log2(200) is below 8
+        assert!(report.mean_hops() < 8.0, "{}", report.mean_hops());
+
+        // more keys than identifiers could never all be picked
+        let config = SimConfig {
+            nodes: 10,
+            keys: 300,
+            ..SimConfig::default()
+        };
+        assert!(matches!(
+            Simulation::<8>::new(config),
+            Err(Error::InvalidConfig(_))
+        ));
+    }
+
+    #[test]
+    fn test_simulation_is_deterministic() {
+        let config = SimConfig {
+            seed: 7,
+            nodes: 100,
+            keys: 300,
+            duration: Duration::from_secs(30),
+            loss: 0.01,
+            churn: Churn {
+                joins_per_sec: 0.5,
+                leaves_per_sec: 0.2,
+                crashes_per_sec: 0.2,
+            },
+            partitions: vec![Partition {
+                start: Duration::from_secs(10),
+                end: Duration::from_secs(15),
+                fraction: 0.2,
+            }],
+            node_config: Config {
+                replication_factor: 3,
+                ..Config::default()
+            },
+            ..SimConfig::default()
+        };
+        let report = Simulation::<32>::new(config.clone())
+            .unwrap()
+            .run()
+            .unwrap();
+        println!("{}", report);
+        assert!(report.lost_messages > 0);
+        assert!(report.failed_lookups > 0);
+        assert!(report.joins + report.failed_joins > 0);
+        assert!(report.crashes > 0);
+        assert!(report.failure_rate() < 0.5);
+        assert_eq!(
+            Simulation::<32>::new(config).unwrap().run().unwrap(),
+            report
+        );
+    }
+
+    #[test]
+    fn test_simulated_lookup_modes() {
+        let simulate = |mode: LookupMode, loss: f64| {
+            let config = SimConfig {
+                seed: 3,
+                nodes: 100,
+                keys: 200,
+                duration: Duration::from_secs(20),
+                loss,
+                node_config: Config {
+                    lookup_mode: mode,
+                    hop_timeout: Duration::from_millis(200),
+                    ..Config::default()
+                },
+                ..SimConfig::default()
+            };
+            Simulation::<32>::new(config).unwrap().run().unwrap()
+        };
+        let iterative = simulate(LookupMode::Iterative, 0.0);
+        let recursive = simulate(LookupMode::Recursive, 0.0);
+        println!("iterative:\n{}\nrecursive:\n{}", iterative, recursive);
+        // the same hops, but a recursive hop costs one message instead of
+        // the two round trips of an iterative one
+        assert_eq!(iterative.hops, recursive.hops);
+        assert!(recursive.mean_lookup_latency() < iterative.mean_lookup_latency());
+
+        // both route around a lost message, but an iterative lookup sends
+        // more of them to lose
+        let iterative = simulate(LookupMode::Iterative, 0.02);
+        let recursive = simulate(LookupMode::Recursive, 0.02);
+        println!("iterative:\n{}\nrecursive:\n{}", iterative, recursive);
+        assert!(recursive.failure_rate() < iterative.failure_rate());
     }
 }
@@ -0,0 +1,140 @@
+//! A ring of `chord serve` processes on localhost, driven through clients.
+
+use chord::net::TcpTransport;
+use chord::RemoteNode;
+use std::io::{BufRead, BufReader};
+use std::process::{Child, Command, Stdio};
+use std::sync::Arc;
+
+type Client = RemoteNode<u8, String, 8>;
+
+/// A node running in a process of its own, killed when dropped.
+struct Server {
+    process: Child,
+    address: String,
+}
+
+impl Server {
+    /// Starts node `id` on a free port, joining through the node at
+    /// `bootstrap`, and waits until it has joined.
+    fn start(id: u8, bootstrap: Option<&Server>) -> Self {
+        let mut command = Command::new(env!("CARGO_BIN_EXE_chord"));
+        command.args(["serve", &id.to_string(), "127.0.0.1:0"]);
+        if let Some(bootstrap) = bootstrap {
+            command.arg(&bootstrap.address);
+        }
+        let mut process = command.stdout(Stdio::piped()).spawn().unwrap();
+        let mut line = String::new();
+        BufReader::new(process.stdout.as_mut().unwrap())
+            .read_line(&mut line)
+            .unwrap();
+        let prefix = format!("node {} listening on ", id);
+        let address = line
+            .trim()
+            .strip_prefix(&prefix)
+            .unwrap_or_else(|| panic!("node {} failed to start: {:?}", id, line))
+            .to_string();
+        Self { process, address }
+    }
+
+    fn client(&self) -> Client {
+        RemoteNode::connect(&self.address, Arc::new(TcpTransport::default())).unwrap()
+    }
+
+    fn kill(&mut self) {
+        self.process.kill().unwrap();
+        self.process.wait().unwrap();
+    }
+}
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        let _ = self.process.kill();
+        let _ = self.process.wait();
+    }
+}
+
+fn owner(live: &[u8], id: u8) -> u8 {
+    *live.iter().find(|n| **n >= id).unwrap_or(&live[0])
+}
+
+/// Runs the `test_sample_case` operations against a process per node, the
+/// same sequence the unit tests run against threads, then crashes node 160.
+#[test]
+fn test_process_cluster() {
+    let ids = [0u8, 30, 65, 110, 160, 230, 100];
+    let mut servers: Vec<Server> = Vec::new();
+    for id in &ids[..6] {
+        let server = Server::start(*id, servers.last());
+        servers.push(server);
+    }
+    let inserts = [
+        (0, 3u8, 3u8),
+        (1, 200, 0),
+        (2, 123, 0),
+        (3, 45, 3),
+        (4, 99, 0),
+        (2, 60, 10),
+        (0, 50, 8),
+        (3, 100, 5),
+        (3, 101, 4),
+        (3, 102, 6),
+        (5, 240, 8),
+        (5, 250, 10),
+    ];
+    for (i, key, value) in inserts {
+        servers[i]
+            .client()
+            .insert(key, format!("v{}", value))
+            .unwrap();
+    }
+    // node 100 joins after the inserts and takes keys 99 and 100 from 110
+    let server = Server::start(100, Some(&servers[5]));
+    servers.push(server);
+    let clients: Vec<Client> = servers.iter().map(|s| s.client()).collect();
+    for (client, id) in clients.iter().zip(ids) {
+        assert_eq!(client.id(), id);
+    }
+
+    let check = |live: &[usize], lost: &[u8]| {
+        let mut live_ids: Vec<u8> = live.iter().map(|i| ids[*i]).collect();
+        live_ids.sort();
+        for i in live.iter() {
+            let mut held: Vec<u8> = clients[*i]
+                .keys()
+                .unwrap()
+                .iter()
+                .map(|(k, _)| k.id)
+                .collect();
+            held.sort();
+            let mut expected: Vec<u8> = inserts
+                .iter()
+                .map(|(_, key, _)| *key)
+                .filter(|key| !lost.contains(key) && owner(&live_ids, *key) == ids[*i])
+                .collect();
+            expected.sort();
+            assert_eq!(held, expected, "keys of node {}", ids[*i]);
+        }
+        for i in live.iter() {
+            for (_, key, value) in inserts {
+                let expected = (!lost.contains(&key)).then(|| format!("v{}", value));
+                assert_eq!(clients[*i].find(key).unwrap(), expected, "key {}", key);
+            }
+        }
+    };
+    check(&[0, 1, 2, 3, 4, 5, 6], &[]);
+
+    // node 65 hands its keys over
+    clients[2].leave().unwrap();
+    check(&[0, 1, 3, 4, 5, 6], &[]);
+
+    // node 160 fails and its keys are lost
+    servers[4].kill();
+    let live = [0, 1, 3, 5, 6];
+    for _ in 0..2 * 8 {
+        for i in live {
+            let _ = clients[i].tick();
+        }
+    }
+    check(&live, &[123]);
+}